@@ -0,0 +1,77 @@
+use std::{backtrace::BacktraceStatus, panic::Location};
+
+use crate::{ErrorLevel, TracebackError};
+
+/// Converts an `anyhow::Error` into a chain of `TracebackError`s.
+///
+/// Every layer of the anyhow context chain becomes a node, with the outermost context
+/// as the returned error and the root cause as the deepest parent. The nodes get the
+/// location of the conversion, such as the `?` that triggered it, and the `Error`
+/// level. A captured anyhow backtrace is stored in the `backtrace` field.
+///
+/// If the chain wraps a `TracebackError`, that error is taken out as is and the
+/// context layers above it are added on top, so converting back and forth loses
+/// nothing.
+///
+/// ```rust
+/// use anyhow::Context;
+/// use traceback_error::TracebackError;
+///
+/// fn read_config() -> anyhow::Result<String> {
+///     Err(anyhow::anyhow!("file not found")).context("reading config")
+/// }
+///
+/// let mut error = TracebackError::from(read_config().unwrap_err());
+/// assert_eq!(error.message, "reading config");
+/// assert_eq!(error.parent.as_ref().unwrap().message, "file not found");
+/// error.is_handled = true;
+/// ```
+///
+/// The conversion the other way comes from anyhow's blanket implementation, and
+/// keeps the original error downcastable:
+///
+/// ```rust
+/// let mut error = traceback_error::traceback!("Query failed");
+/// error.is_handled = true;
+///
+/// let wrapped = anyhow::Error::from(error).context("loading users");
+/// let error = wrapped.downcast_ref::<traceback_error::TracebackError>().unwrap();
+/// assert_eq!(error.message, "Query failed");
+/// ```
+impl From<anyhow::Error> for TracebackError {
+    #[track_caller]
+    fn from(mut error: anyhow::Error) -> Self {
+        let location = Location::caller();
+        let backtrace = match error.backtrace().status() {
+            BacktraceStatus::Captured => Some(error.backtrace().to_string()),
+            _ => None,
+        };
+        // The context layers down to a wrapped `TracebackError`, or the whole chain
+        let messages: Vec<String> = error
+            .chain()
+            .take_while(|cause| !cause.is::<TracebackError>())
+            .map(|cause| cause.to_string())
+            .collect();
+        // Leaves a default error behind, which is never reported
+        let mut node = error.downcast_mut::<TracebackError>().map(std::mem::take);
+
+        for message in messages.into_iter().rev() {
+            let error = TracebackError::new(
+                message,
+                location.file().to_string(),
+                location.line(),
+                ErrorLevel::Error,
+            );
+            node = Some(match node {
+                Some(parent) => error.with_parent(parent),
+                None => error,
+            });
+        }
+        // An anyhow chain always has at least one error in it
+        let mut node = node.unwrap_or_default();
+        if node.backtrace.is_none() {
+            node.backtrace = backtrace;
+        }
+        node
+    }
+}
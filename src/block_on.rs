@@ -28,15 +28,12 @@ pub(crate) fn block_on<F: Future>(mut func: F) -> F::Output {
     // Create a Context for polling the Future with the given Waker.
     let mut ctx = Context::from_waker(&waker);
 
-    // Poll the Future using the Context.
-    let poll = Pin::new(&mut func).poll(&mut ctx);
-
     // Continuously poll the Future until it's ready.
-    let result = loop {
-        match poll {
+    // Return the result of the Future once it's ready.
+    loop {
+        match Pin::new(&mut func).poll(&mut ctx) {
             Poll::Pending => continue,
             Poll::Ready(result) => break result,
         };
-    };
-    result // Return the result of the Future once it's ready.
+    }
 }
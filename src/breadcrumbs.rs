@@ -0,0 +1,248 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use crate::ErrorLevel;
+
+/// The number of breadcrumbs kept unless changed with `set_breadcrumb_capacity`.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_CAPACITY);
+static PER_THREAD: AtomicBool = AtomicBool::new(false);
+static GLOBAL: Mutex<VecDeque<Breadcrumb>> = Mutex::new(VecDeque::new());
+
+thread_local! {
+    static LOCAL: RefCell<VecDeque<Breadcrumb>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// An event that happened before an error, recorded to help understand how the
+/// program got there.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Breadcrumb {
+    pub timestamp: DateTime<Utc>,
+    pub category: String,
+    pub message: String,
+    pub level: ErrorLevel,
+    #[serde(default)]
+    pub data: Map<String, Value>,
+}
+
+impl Breadcrumb {
+    pub fn new(category: &str, message: &str, level: ErrorLevel) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            category: category.to_string(),
+            message: message.to_string(),
+            level,
+            data: Map::new(),
+        }
+    }
+
+    pub fn with_data(mut self, data: Map<String, Value>) -> Self {
+        self.data = data;
+        self
+    }
+}
+
+/// Records a breadcrumb, dropping the oldest one if the buffer is full.
+///
+/// The buffer's contents are copied into the `breadcrumbs` field of every
+/// `TracebackError` when it is handled.
+pub fn add(breadcrumb: Breadcrumb) {
+    let capacity = CAPACITY.load(Ordering::Relaxed);
+    let push = |buffer: &mut VecDeque<Breadcrumb>| {
+        if capacity == 0 {
+            return;
+        }
+        while buffer.len() >= capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(breadcrumb);
+    };
+    if PER_THREAD.load(Ordering::Relaxed) {
+        LOCAL.with(|buffer| push(&mut buffer.borrow_mut()));
+    } else {
+        push(&mut GLOBAL.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+/// Returns the breadcrumbs currently in the buffer, oldest first.
+pub fn snapshot() -> Vec<Breadcrumb> {
+    if PER_THREAD.load(Ordering::Relaxed) {
+        LOCAL.with(|buffer| buffer.borrow().iter().cloned().collect())
+    } else {
+        let buffer = GLOBAL.lock().unwrap_or_else(|e| e.into_inner());
+        buffer.iter().cloned().collect()
+    }
+}
+
+/// Empties the buffer the current thread records to.
+pub fn clear() {
+    if PER_THREAD.load(Ordering::Relaxed) {
+        LOCAL.with(|buffer| buffer.borrow_mut().clear());
+    } else {
+        GLOBAL.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+/// Sets how many breadcrumbs are kept. Setting it to 0 disables breadcrumbs.
+pub fn set_breadcrumb_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+}
+
+/// Chooses between one buffer shared by the whole program, the default, and one
+/// buffer per thread.
+///
+/// With per-thread buffers, an error only carries the breadcrumbs recorded on the
+/// thread it is handled on.
+pub fn set_per_thread(per_thread: bool) {
+    PER_THREAD.store(per_thread, Ordering::Relaxed);
+}
+
+/// Records a breadcrumb with a category and a message, which can be formatted like
+/// `format!`.
+///
+/// ```rust
+/// use traceback_error::breadcrumb;
+///
+/// breadcrumb!("db", "query started");
+/// breadcrumb!("http", "GET {} returned {}", "/users", 200);
+///
+/// let crumbs = traceback_error::breadcrumbs::snapshot();
+/// assert_eq!(crumbs.last().unwrap().message, "GET /users returned 200");
+/// ```
+#[macro_export]
+macro_rules! breadcrumb {
+    ($category:expr, $msg:expr) => {
+        $crate::breadcrumbs::add($crate::breadcrumbs::Breadcrumb::new(
+            &$category.to_string(),
+            &$msg.to_string(),
+            $crate::ErrorLevel::Log,
+        ))
+    };
+    ($category:expr, $fmt:expr, $($arg:tt)+) => {
+        $crate::breadcrumbs::add($crate::breadcrumbs::Breadcrumb::new(
+            &$category.to_string(),
+            &format!($fmt, $($arg)+),
+            $crate::ErrorLevel::Log,
+        ))
+    };
+}
+
+/// A `log::Log` implementation that records every log record as a breadcrumb, and
+/// optionally passes it on to another logger.
+///
+/// ```rust
+/// let logger = traceback_error::breadcrumbs::BreadcrumbLogger::new(log::LevelFilter::Info);
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Info);
+///
+/// log::info!(target: "db", "query started");
+/// let crumbs = traceback_error::breadcrumbs::snapshot();
+/// assert_eq!(crumbs.last().unwrap().category, "db");
+/// ```
+#[cfg(feature = "log")]
+pub struct BreadcrumbLogger {
+    level: log::LevelFilter,
+    inner: Option<Box<dyn log::Log>>,
+}
+
+#[cfg(feature = "log")]
+impl BreadcrumbLogger {
+    /// Records records at or above `level` as breadcrumbs.
+    pub fn new(level: log::LevelFilter) -> Self {
+        Self { level, inner: None }
+    }
+
+    /// Passes every record on to `inner` after recording it.
+    pub fn with_inner(mut self, inner: Box<dyn log::Log>) -> Self {
+        self.inner = Some(inner);
+        self
+    }
+}
+
+#[cfg(feature = "log")]
+impl log::Log for BreadcrumbLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
+            || self
+                .inner
+                .as_ref()
+                .is_some_and(|inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= self.level {
+            add(Breadcrumb::new(
+                record.target(),
+                &record.args().to_string(),
+                record.level().into(),
+            ));
+        }
+        if let Some(inner) = &self.inner {
+            inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
+
+/// A `tracing_subscriber::Layer` that records every event as a breadcrumb, with the
+/// event's fields other than the message as the breadcrumb's data.
+///
+/// ```rust
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry()
+///     .with(traceback_error::breadcrumbs::BreadcrumbLayer::new(tracing::Level::INFO));
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::info!(table = "users", "query started");
+/// });
+/// ```
+#[cfg(feature = "tracing")]
+pub struct BreadcrumbLayer {
+    level: tracing::Level,
+}
+
+#[cfg(feature = "tracing")]
+impl BreadcrumbLayer {
+    /// Records events at or above `level` as breadcrumbs.
+    pub fn new(level: tracing::Level) -> Self {
+        Self { level }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for BreadcrumbLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let metadata = event.metadata();
+        // tracing orders levels by verbosity, so more severe levels compare as smaller
+        if *metadata.level() > self.level {
+            return;
+        }
+        let mut fields = crate::tracing_support::FieldVisitor::default();
+        event.record(&mut fields);
+        add(Breadcrumb::new(
+            metadata.target(),
+            &fields.message.unwrap_or_default(),
+            (*metadata.level()).into(),
+        )
+        .with_data(fields.fields));
+    }
+}
@@ -0,0 +1,198 @@
+use serde_json::{Map, Value};
+use std::{
+    cell::RefCell,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::merge_json_objects;
+
+thread_local! {
+    static STACK: RefCell<Vec<Map<String, Value>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps a set of context values active on the current thread until it is dropped.
+///
+/// Created by `push` and the `context!` macro. Guards are not `Send`, since the values
+/// belong to the thread they were pushed on.
+#[must_use = "the context is removed again as soon as the guard is dropped"]
+pub struct ContextGuard {
+    depth: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        // Also removes anything pushed after this guard that is still around
+        STACK.with(|stack| stack.borrow_mut().truncate(self.depth));
+    }
+}
+
+/// Pushes context values that are merged into the `extra_data` of every
+/// `TracebackError` created on this thread while the returned guard lives.
+///
+/// Values pushed later are merged over earlier ones, and data added with
+/// `with_extra_data` is merged over the context, all with the same semantics as
+/// `with_extra_data` itself.
+pub fn push(values: Map<String, Value>) -> ContextGuard {
+    STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        stack.push(values);
+        ContextGuard {
+            depth: stack.len() - 1,
+            _not_send: PhantomData,
+        }
+    })
+}
+
+/// Returns the merged context values currently active on this thread.
+pub fn current() -> Map<String, Value> {
+    STACK.with(|stack| {
+        stack.borrow().iter().fold(Map::new(), |merged, values| {
+            merge_json_objects(merged, values.clone())
+        })
+    })
+}
+
+/// A future that has its context values active whenever it is polled.
+///
+/// A `ContextGuard` held across an `.await` would leak its values into every other
+/// task polled on the same thread in the meantime, so async code should use
+/// `scope` or `inherit` instead.
+pub struct WithContext<F> {
+    values: Map<String, Value>,
+    future: F,
+}
+
+impl<F: Future> Future for WithContext<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the future is never moved out of `self`, and `values` is not pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let _guard = push(this.values.clone());
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        future.poll(cx)
+    }
+}
+
+/// Runs a future with the given context values active, like a task-local `push`.
+///
+/// ```rust
+/// use std::{
+///     future::Future,
+///     pin::pin,
+///     sync::Arc,
+///     task::{Context, Poll, Wake},
+/// };
+/// use traceback_error::{context, serde_json::json};
+///
+/// // Any executor works, this one just polls until the future is ready
+/// fn run<F: Future>(future: F) -> F::Output {
+///     struct Noop;
+///     impl Wake for Noop {
+///         fn wake(self: Arc<Self>) {}
+///     }
+///     let waker = Arc::new(Noop).into();
+///     let mut cx = Context::from_waker(&waker);
+///     let mut future = pin!(future);
+///     loop {
+///         if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+///             return output;
+///         }
+///     }
+/// }
+///
+/// async fn handle_request() {
+///     let mut error = traceback_error::traceback!("Request failed");
+///     assert_eq!(error.extra_data["request_id"], json!(42));
+///     error.is_handled = true;
+/// }
+///
+/// let values = json!({ "request_id": 42 }).as_object().unwrap().clone();
+/// run(context::scope(values, handle_request()));
+///
+/// // The values were only active while the task was polled
+/// assert!(context::current().get("request_id").is_none());
+/// ```
+pub fn scope<F: Future>(values: Map<String, Value>, future: F) -> WithContext<F> {
+    WithContext { values, future }
+}
+
+/// Runs a future with the context values active on this thread right now, so a
+/// spawned task keeps the context of the code that spawned it.
+///
+/// ```rust
+/// use std::{
+///     future::Future,
+///     pin::pin,
+///     sync::Arc,
+///     task::{Context, Poll, Wake},
+/// };
+/// use traceback_error::{context, serde_json::json};
+///
+/// // Any executor works, this one just polls until the future is ready
+/// fn run<F: Future>(future: F) -> F::Output {
+///     struct Noop;
+///     impl Wake for Noop {
+///         fn wake(self: Arc<Self>) {}
+///     }
+///     let waker = Arc::new(Noop).into();
+///     let mut cx = Context::from_waker(&waker);
+///     let mut future = pin!(future);
+///     loop {
+///         if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+///             return output;
+///         }
+///     }
+/// }
+///
+/// let guard = traceback_error::context!(tenant = "acme");
+/// let task = context::inherit(async {
+///     let mut error = traceback_error::traceback!("Sync failed");
+///     assert_eq!(error.extra_data["tenant"], json!("acme"));
+///     error.is_handled = true;
+/// });
+/// drop(guard);
+///
+/// // The task keeps the values active when it was created
+/// run(task);
+/// assert!(context::current().is_empty());
+/// ```
+pub fn inherit<F: Future>(future: F) -> WithContext<F> {
+    scope(current(), future)
+}
+
+/// Pushes key/value pairs onto the context stack of the current thread, returning a
+/// guard that removes them again when dropped.
+///
+/// Every `TracebackError` created while the guard lives gets the values merged into
+/// its `extra_data`. Values can be anything that implements `Serialize`. A value that
+/// fails to serialize is stored as a `failed to serialize: ...` message instead.
+///
+/// ```rust
+/// use traceback_error::serde_json::json;
+///
+/// let request_id = 42;
+/// let _guard = traceback_error::context!(request_id = request_id, tenant = "acme");
+///
+/// let mut error = traceback_error::traceback!("Query failed");
+/// assert_eq!(error.extra_data["request_id"], json!(42));
+/// assert_eq!(error.extra_data["tenant"], json!("acme"));
+/// error.is_handled = true;
+/// ```
+#[macro_export]
+macro_rules! context {
+    ($($key:ident = $value:expr),+ $(,)?) => {{
+        let mut values = $crate::serde_json::Map::new();
+        $(
+            let value = $crate::serde_json::to_value(&$value).unwrap_or_else(|e| {
+                $crate::serde_json::Value::String(format!("failed to serialize: {}", e))
+            });
+            values.insert(stringify!($key).to_string(), value);
+        )+
+        $crate::context::push(values)
+    }};
+}
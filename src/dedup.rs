@@ -0,0 +1,273 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{dispatch, fingerprint::Fingerprint, timer::Timer, TracebackError};
+
+struct DedupWindow {
+    opened: Instant,
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    occurrences: u64,
+    // The most recent duplicate, which becomes the summary when the window closes
+    latest: Option<TracebackError>,
+}
+
+struct DedupState {
+    window: Option<Duration>,
+    windows: HashMap<Fingerprint, DedupWindow>,
+}
+
+static STATE: Mutex<Option<DedupState>> = Mutex::new(None);
+
+static TIMER: Timer = Timer::new("traceback-dedup", next_deadline, report_expired);
+
+/// Collapses repeated errors into counted reports.
+///
+/// With a window set, the first error of each fingerprint is handed to the callback
+/// immediately. Every further error with the same fingerprint within the window is
+/// held back and counted. Once the window has passed, a single summary is reported:
+/// the last duplicate, with `occurrence_count`, `first_seen` and `last_seen` added
+/// to its `metadata`.
+///
+/// Summaries are emitted from a background thread as soon as their window has passed,
+/// or right away when `flush_dedup` is called. While a `testing::capture` is alive,
+/// the background thread waits, and summaries go out with the next error instead. Passing `None` disables deduplication
+/// and flushes any pending summaries. Windows that are still open when the program
+/// exits are lost, unless they are flushed, for example with a `flush_guard`.
+///
+/// ```rust
+/// use std::time::Duration;
+/// use traceback_error::{dedup, testing, traceback};
+///
+/// dedup::set_dedup_window(Some(Duration::from_secs(60)));
+/// let capture = testing::capture();
+///
+/// // Only the first of these reaches the callback right away
+/// for attempt in 0..100 {
+///     traceback!(format!("attempt {} failed", attempt)).report();
+/// }
+/// assert_eq!(capture.errors().len(), 1);
+///
+/// // Report the summary now rather than when the window has passed
+/// dedup::flush_dedup();
+/// let errors = capture.take();
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(errors[0].message, "attempt 0 failed");
+/// assert_eq!(errors[1].message, "attempt 99 failed");
+/// assert_eq!(errors[1].metadata["occurrence_count"], 100);
+/// ```
+///
+/// Without a flush, the summary arrives on its own once the window has passed:
+///
+/// ```rust
+/// use std::{sync::Mutex, thread, time::Duration};
+/// use traceback_error::{dedup, traceback, TracebackError};
+///
+/// static RECEIVED: Mutex<Vec<TracebackError>> = Mutex::new(Vec::new());
+///
+/// fn record(error: TracebackError) {
+///     RECEIVED.lock().unwrap().push(error);
+/// }
+///
+/// fn main() {
+///     traceback_error::set_traceback!(record);
+///     dedup::set_dedup_window(Some(Duration::from_millis(50)));
+///     for _ in 0..3 {
+///         traceback!("Connection reset").report();
+///     }
+///
+///     thread::sleep(Duration::from_millis(500));
+///     let received = RECEIVED.lock().unwrap();
+///     assert_eq!(received.len(), 2);
+///     assert_eq!(received[1].metadata["occurrence_count"], 3);
+/// }
+/// ```
+///
+/// While capturing, a summary that became due is captured with the next error:
+///
+/// ```rust
+/// use std::{thread, time::Duration};
+/// use traceback_error::{dedup, testing, traceback};
+///
+/// dedup::set_dedup_window(Some(Duration::from_millis(50)));
+/// let capture = testing::capture();
+/// for _ in 0..3 {
+///     traceback!("Connection reset").report();
+/// }
+///
+/// thread::sleep(Duration::from_millis(200));
+/// assert_eq!(capture.take().len(), 1);
+///
+/// traceback!("Connection refused").report();
+/// let errors = capture.take();
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(errors[0].metadata["occurrence_count"], 3);
+/// assert_eq!(errors[1].message, "Connection refused");
+/// ```
+pub fn set_dedup_window(window: Option<Duration>) {
+    if window.is_none() {
+        flush_dedup();
+    }
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    match state.as_mut() {
+        Some(state) => {
+            state.window = window;
+            TIMER.wake();
+        }
+        None => {
+            *state = Some(DedupState {
+                window,
+                windows: HashMap::new(),
+            })
+        }
+    }
+}
+
+/// Reports the summaries of every open window right away, and forgets the windows.
+///
+/// This is useful right before the program exits, so no counts are lost.
+pub fn flush_dedup() {
+    let summaries: Vec<TracebackError> = {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        match state.as_mut() {
+            Some(state) => state
+                .windows
+                .drain()
+                .filter_map(|(_, w)| summary(w))
+                .collect(),
+            None => Vec::new(),
+        }
+    };
+    for summary in summaries {
+        dispatch::invoke_callback(summary);
+    }
+}
+
+/// Flushes the dedup summaries when dropped, see `flush_guard`.
+#[must_use = "the summaries are flushed when the guard is dropped"]
+pub struct FlushGuard {
+    _private: (),
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        flush_dedup();
+    }
+}
+
+/// Returns a guard that calls `flush_dedup` when dropped, such as at the end of `main`.
+///
+/// ```rust
+/// let _flush = traceback_error::dedup::flush_guard();
+/// // Summaries of windows still open when `_flush` goes out of scope are reported then
+/// ```
+pub fn flush_guard() -> FlushGuard {
+    FlushGuard { _private: () }
+}
+
+/// Returns the errors that should reach the callback for the given error.
+pub(crate) fn process(error: TracebackError) -> Vec<TracebackError> {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = match state.as_mut() {
+        Some(state) => state,
+        None => return vec![error],
+    };
+    let window = match state.window {
+        Some(window) => window,
+        None => return vec![error],
+    };
+
+    let mut out = take_expired(state, window);
+
+    let fingerprint = error.fingerprint();
+    match state.windows.get_mut(&fingerprint) {
+        Some(open) => {
+            open.occurrences += 1;
+            open.last_seen = error.time_created;
+            open.latest = Some(error);
+            if open.occurrences == 2 {
+                TIMER.wake();
+            }
+        }
+        None => {
+            state.windows.insert(
+                fingerprint,
+                DedupWindow {
+                    opened: Instant::now(),
+                    first_seen: error.time_created,
+                    last_seen: error.time_created,
+                    occurrences: 1,
+                    latest: None,
+                },
+            );
+            out.push(error);
+        }
+    }
+    out
+}
+
+// Closes the windows that have passed, and returns their summaries
+fn take_expired(state: &mut DedupState, window: Duration) -> Vec<TracebackError> {
+    let expired: Vec<Fingerprint> = state
+        .windows
+        .iter()
+        .filter(|(_, w)| w.opened.elapsed() >= window)
+        .map(|(fingerprint, _)| *fingerprint)
+        .collect();
+    expired
+        .iter()
+        .filter_map(|fingerprint| state.windows.remove(fingerprint))
+        .filter_map(summary)
+        .collect()
+}
+
+// When the earliest window holding duplicates closes
+fn next_deadline() -> Option<Instant> {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = state.as_ref()?;
+    let window = state.window?;
+    state
+        .windows
+        .values()
+        .filter(|w| w.latest.is_some())
+        .map(|w| w.opened + window)
+        .min()
+}
+
+fn report_expired() {
+    let summaries = {
+        let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+        match state.as_mut() {
+            Some(state) => match state.window {
+                Some(window) => take_expired(state, window),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    };
+    for summary in summaries {
+        dispatch::invoke_callback(summary);
+    }
+}
+
+// Windows that only saw their first occurrence have nothing left to report
+fn summary(window: DedupWindow) -> Option<TracebackError> {
+    let mut error = window.latest?;
+    error
+        .metadata
+        .insert("occurrence_count".to_string(), json!(window.occurrences));
+    error.metadata.insert(
+        "first_seen".to_string(),
+        json!(window.first_seen.to_rfc3339()),
+    );
+    error.metadata.insert(
+        "last_seen".to_string(),
+        json!(window.last_seen.to_rfc3339()),
+    );
+    Some(error)
+}
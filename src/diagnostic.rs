@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::TracebackError;
+
+/// Points at the part of the source text an error is about, as a byte offset and
+/// length, with an optional message drawn next to the underline.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Label {
+    pub offset: usize,
+    pub len: usize,
+    pub message: Option<String>,
+}
+
+/// Describes an error in some source text the program read, like a config file or a
+/// script, rather than in the program's own code.
+///
+/// Attach one to a `TracebackError` with `with_diagnostic`, and draw it with `render`.
+///
+/// ```rust
+/// use traceback_error::diagnostic::{render, Diagnostic};
+///
+/// let source = "name = \"api\"\nport = \"abc\"\n";
+/// let mut error = traceback_error::traceback!("Invalid port").with_diagnostic(
+///     Diagnostic::new()
+///         .with_code("config::port")
+///         .with_source("config.toml", source)
+///         .with_label(20, 5, "expected a number")
+///         .with_help("ports are numbers between 1 and 65535"),
+/// );
+/// error.level = traceback_error::ErrorLevel::Error;
+/// error.is_handled = true;
+///
+/// assert_eq!(
+///     render(&error),
+///     "error[config::port]: Invalid port
+///  --> config.toml:2:8
+///   |
+/// 2 | port = \"abc\"
+///   |        ^^^^^ expected a number
+///   |
+///   = help: ports are numbers between 1 and 65535"
+/// );
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Diagnostic {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub source_name: Option<String>,
+    #[serde(default)]
+    pub source_text: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    #[serde(default)]
+    pub help: Option<String>,
+    #[serde(default)]
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a code identifying the kind of error, like `config::port`.
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    /// Sets the name, usually a path, and the full text of the source the labels
+    /// point into.
+    pub fn with_source(mut self, name: &str, text: &str) -> Self {
+        self.source_name = Some(name.to_string());
+        self.source_text = Some(text.to_string());
+        self
+    }
+
+    /// Underlines `len` bytes of the source text, starting at byte `offset`.
+    pub fn with_label(mut self, offset: usize, len: usize, message: &str) -> Self {
+        self.labels.push(Label {
+            offset,
+            len,
+            message: Some(message.to_string()),
+        });
+        self
+    }
+
+    /// Sets a suggestion for how to fix the error.
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// Adds a note with more information about the error.
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+}
+
+/// Renders an error with its diagnostic, in the style of compiler errors.
+///
+/// The header holds the level, the code and the message. It is followed by the
+/// position of the first label, the labeled source lines with their underlines, the
+/// help and the notes. Errors without source text point at their `file` and `line`
+/// instead.
+pub fn render(error: &TracebackError) -> String {
+    let empty = Diagnostic::default();
+    let diagnostic = error.diagnostic.as_ref().unwrap_or(&empty);
+    let mut out = String::new();
+
+    match &diagnostic.code {
+        Some(code) => write!(out, "{}[{}]: {}", error.level, code, error.message),
+        None => write!(out, "{}: {}", error.level, error.message),
+    }
+    .unwrap();
+
+    // Labels grouped by the index of the line they start on
+    let mut lines: BTreeMap<usize, Vec<&Label>> = BTreeMap::new();
+    if let Some(text) = &diagnostic.source_text {
+        for label in &diagnostic.labels {
+            let (line, _) = line_column(text, label.offset);
+            lines.entry(line).or_default().push(label);
+        }
+    }
+    let width = lines
+        .keys()
+        .next_back()
+        .map_or(1, |line| (line + 1).to_string().len());
+    let pad = " ".repeat(width);
+
+    let name = diagnostic.source_name.as_deref().unwrap_or("<source>");
+    match (&diagnostic.source_text, diagnostic.labels.first()) {
+        (Some(text), Some(first)) => {
+            let (line, column) = line_column(text, first.offset);
+            write!(out, "\n{}--> {}:{}:{}", pad, name, line + 1, column + 1)
+        }
+        _ if diagnostic.source_name.is_some() => write!(out, "\n{}--> {}", pad, name),
+        _ => write!(out, "\n{}--> {}:{}", pad, error.file, error.line),
+    }
+    .unwrap();
+
+    if let Some(text) = &diagnostic.source_text {
+        if !lines.is_empty() {
+            write!(out, "\n{} |", pad).unwrap();
+        }
+        let source_lines: Vec<&str> = text.split('\n').collect();
+        for (&line, labels) in &lines {
+            let content = source_lines[line].trim_end_matches('\r');
+            write!(out, "\n{:>width$} | {}", line + 1, content, width = width).unwrap();
+            let line_start = line_start_of(text, line);
+            for label in labels {
+                let start = clamp(text, label.offset);
+                let end = clamp(text, label.offset.saturating_add(label.len))
+                    .min(line_start + content.len())
+                    .max(start);
+                let column = text[line_start..start].chars().count();
+                let carets = text[start..end].chars().count().max(1);
+                let underline = format!(
+                    "{}{} {}",
+                    " ".repeat(column),
+                    "^".repeat(carets),
+                    label.message.as_deref().unwrap_or_default()
+                );
+                write!(out, "\n{} | {}", pad, underline.trim_end()).unwrap();
+            }
+        }
+        if !lines.is_empty() {
+            write!(out, "\n{} |", pad).unwrap();
+        }
+    }
+
+    if let Some(help) = &diagnostic.help {
+        write!(out, "\n{} = help: {}", pad, help).unwrap();
+    }
+    for note in &diagnostic.notes {
+        write!(out, "\n{} = note: {}", pad, note).unwrap();
+    }
+    out
+}
+
+// Moves an offset into the text and back onto a character boundary
+fn clamp(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+// The zero-based line and column, in characters, of a byte offset
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..clamp(text, offset)];
+    let line = before.matches('\n').count();
+    let column = match before.rfind('\n') {
+        Some(newline) => before[newline + 1..].chars().count(),
+        None => before.chars().count(),
+    };
+    (line, column)
+}
+
+// The byte offset the given zero-based line starts at
+fn line_start_of(text: &str, line: usize) -> usize {
+    if line == 0 {
+        return 0;
+    }
+    text.match_indices('\n')
+        .nth(line - 1)
+        .map_or(text.len(), |(newline, _)| newline + 1)
+}
+
+#[cfg(feature = "miette")]
+impl miette::SourceCode for Diagnostic {
+    fn read_span<'a>(
+        &'a self,
+        span: &miette::SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn miette::SpanContents<'a> + 'a>, miette::MietteError> {
+        let text = self.source_text.as_deref().unwrap_or_default();
+        let contents = text.read_span(span, context_lines_before, context_lines_after)?;
+        Ok(Box::new(miette::MietteSpanContents::new_named(
+            self.source_name.clone().unwrap_or_default(),
+            contents.data(),
+            *contents.span(),
+            contents.line(),
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
+
+/// Lets `miette` render errors with a diagnostic. The notes are shown after the help,
+/// as miette has no place of its own for them.
+///
+/// ```rust
+/// use traceback_error::diagnostic::Diagnostic;
+///
+/// let mut error = traceback_error::traceback!("Invalid port").with_diagnostic(
+///     Diagnostic::new()
+///         .with_source("config.toml", "port = \"abc\"")
+///         .with_label(7, 5, "expected a number"),
+/// );
+/// error.is_handled = true;
+///
+/// let labels: Vec<_> = miette::Diagnostic::labels(&error).unwrap().collect();
+/// assert_eq!(labels[0].offset(), 7);
+/// ```
+#[cfg(feature = "miette")]
+impl miette::Diagnostic for TracebackError {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        let code = self.diagnostic.as_ref()?.code.as_ref()?;
+        Some(Box::new(code))
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(match self.level.severity() {
+            35..=u8::MAX => miette::Severity::Error,
+            30..=34 => miette::Severity::Warning,
+            _ => miette::Severity::Advice,
+        })
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        let diagnostic = self.diagnostic.as_ref()?;
+        let mut help: Vec<String> = diagnostic.help.iter().cloned().collect();
+        help.extend(
+            diagnostic
+                .notes
+                .iter()
+                .map(|note| format!("note: {}", note)),
+        );
+        if help.is_empty() {
+            None
+        } else {
+            Some(Box::new(help.join("\n")))
+        }
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        let diagnostic = self.diagnostic.as_ref()?;
+        diagnostic.source_text.as_ref()?;
+        Some(diagnostic)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        let diagnostic = self.diagnostic.as_ref()?;
+        if diagnostic.labels.is_empty() {
+            return None;
+        }
+        Some(Box::new(diagnostic.labels.iter().map(|label| {
+            miette::LabeledSpan::new(label.message.clone(), label.offset, label.len)
+        })))
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{
+    block_on, breadcrumbs, dedup, default_callback, enrich, filter, rate_limit,
+    set_callback::TracebackCallbackType, testing, TracebackError, TRACEBACK_ERROR_CALLBACK,
+};
+
+/// Hands an unhandled error to the traceback callback, unless it is filtered out by
+/// level, or held back by deduplication or rate limiting. Any pending dedup summaries
+/// and suppression reports are handed over along with it.
+pub(crate) fn dispatch(mut error: TracebackError) {
+    if !filter::is_enabled(error.module_path.as_deref(), &error.level) {
+        error.is_handled = true;
+        return;
+    }
+    error.fingerprint = Some(error.fingerprint());
+    error.is_handled = true;
+    for error in dedup::process(error) {
+        for error in rate_limit::process(error) {
+            invoke_callback(error);
+        }
+    }
+}
+
+/// Enriches an error and hands it to the traceback callback, unless `testing::capture`
+/// takes it. Enrichment is left until here so errors held back never pay for it.
+pub(crate) fn invoke_callback(error: TracebackError) {
+    let mut error = error.with_env_vars();
+    if error.breadcrumbs.is_empty() {
+        error.breadcrumbs = breadcrumbs::snapshot();
+    }
+    enrich::run_enrichers(&mut error);
+    let error = match testing::intercept(error) {
+        Some(error) => error,
+        None => return,
+    };
+    unsafe {
+        let callback: Option<&mut TracebackCallbackType> =
+            (*std::ptr::addr_of_mut!(TRACEBACK_ERROR_CALLBACK)).as_mut();
+        match callback {
+            Some(TracebackCallbackType::Async(ref mut f)) => {
+                block_on::block_on(f.call(error)); // bad practice, fix later
+            }
+            Some(TracebackCallbackType::Sync(ref mut f)) => {
+                f.call(error);
+            }
+            None => {
+                default_callback(error);
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::{Arc, Mutex};
+
+use crate::TracebackError;
+
+/// An enricher adds information to a `TracebackError` right before it reaches the
+/// traceback callback.
+///
+/// Enrichers run in the order they were added, after the environment variables have
+/// been captured. They usually write into the error's `metadata` map, which is kept
+/// apart from the user-provided `extra_data`.
+///
+/// ```rust
+/// use traceback_error::{enrich::TracebackEnricher, serde_json::json, TracebackError};
+///
+/// struct Version;
+///
+/// impl TracebackEnricher for Version {
+///     fn enrich(&self, error: &mut TracebackError) {
+///         error.metadata.insert("version".to_string(), json!("1.2.3"));
+///     }
+/// }
+///
+/// traceback_error::enrich::add_enricher(Version);
+/// ```
+///
+/// Errors held back by deduplication or rate limiting are not enriched:
+///
+/// ```rust
+/// use std::{
+///     sync::atomic::{AtomicUsize, Ordering},
+///     time::Duration,
+/// };
+/// use traceback_error::{dedup, enrich::TracebackEnricher, testing, traceback, TracebackError};
+///
+/// static RUNS: AtomicUsize = AtomicUsize::new(0);
+///
+/// struct Counter;
+///
+/// impl TracebackEnricher for Counter {
+///     fn enrich(&self, _error: &mut TracebackError) {
+///         RUNS.fetch_add(1, Ordering::SeqCst);
+///     }
+/// }
+///
+/// traceback_error::enrich::add_enricher(Counter);
+/// dedup::set_dedup_window(Some(Duration::from_secs(60)));
+/// let _capture = testing::capture();
+/// for _ in 0..3 {
+///     traceback!("Connection reset").report();
+/// }
+/// assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+///
+/// // The summary is enriched when it is handed over
+/// dedup::flush_dedup();
+/// assert_eq!(RUNS.load(Ordering::SeqCst), 2);
+/// ```
+pub trait TracebackEnricher {
+    fn enrich(&self, error: &mut TracebackError);
+}
+
+type SharedEnricher = Arc<dyn TracebackEnricher + Send + Sync>;
+
+static ENRICHERS: Mutex<Vec<SharedEnricher>> = Mutex::new(Vec::new());
+
+/// Registers an enricher that runs on every error before it is handed to the callback.
+pub fn add_enricher<E: TracebackEnricher + Send + Sync + 'static>(enricher: E) {
+    let mut enrichers = ENRICHERS.lock().unwrap_or_else(|e| e.into_inner());
+    enrichers.push(Arc::new(enricher));
+}
+
+/// Removes all registered enrichers.
+pub fn clear_enrichers() {
+    let mut enrichers = ENRICHERS.lock().unwrap_or_else(|e| e.into_inner());
+    enrichers.clear();
+}
+
+pub(crate) fn run_enrichers(error: &mut TracebackError) {
+    // Take a snapshot so enrichers can create and drop errors of their own
+    // without deadlocking on the registry
+    let enrichers: Vec<SharedEnricher> =
+        ENRICHERS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    for enricher in enrichers {
+        enricher.enrich(error);
+    }
+}
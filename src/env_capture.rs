@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Names matching any of these patterns have their values replaced with `[REDACTED]`
+/// unless the redaction patterns are overridden with `set_env_redact_patterns`.
+pub const DEFAULT_REDACT_PATTERNS: &[&str] =
+    &["*TOKEN*", "*PASSWORD*", "*KEY*", "*SECRET*", "*CREDENTIAL*"];
+
+const REDACTED: &str = "[REDACTED]";
+
+static ALLOWLIST: Mutex<Vec<String>> = Mutex::new(Vec::new());
+// None means DEFAULT_REDACT_PATTERNS
+static REDACT_PATTERNS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Sets which environment variables are captured into the `env_vars` field of a
+/// `TracebackError` when it is handled.
+///
+/// Patterns are matched against the variable name and may contain `*` (any number of
+/// characters) and `?` (exactly one character). The allowlist is empty by default,
+/// so no variables are captured until it is set.
+///
+/// ```rust
+/// use traceback_error::env_capture;
+///
+/// std::env::set_var("APP_MODE", "production");
+/// std::env::set_var("APP_API_TOKEN", "hunter2");
+/// env_capture::set_env_allowlist(["APP_*"]);
+///
+/// let vars = env_capture::capture_env_vars();
+/// assert_eq!(vars["APP_MODE"], "production");
+/// assert_eq!(vars["APP_API_TOKEN"], "[REDACTED]");
+/// ```
+pub fn set_env_allowlist<I, S>(patterns: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut allowlist = ALLOWLIST.lock().unwrap_or_else(|e| e.into_inner());
+    *allowlist = patterns.into_iter().map(Into::into).collect();
+}
+
+/// Replaces the patterns used to decide which captured variables are redacted.
+///
+/// Redaction patterns are matched case-insensitively. Passing an empty list disables
+/// redaction entirely.
+pub fn set_env_redact_patterns<I, S>(patterns: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let mut redact = REDACT_PATTERNS.lock().unwrap_or_else(|e| e.into_inner());
+    *redact = Some(patterns.into_iter().map(Into::into).collect());
+}
+
+/// Restores the default redaction patterns.
+pub fn reset_env_redact_patterns() {
+    let mut redact = REDACT_PATTERNS.lock().unwrap_or_else(|e| e.into_inner());
+    *redact = None;
+}
+
+/// Captures every environment variable matching the allowlist, redacting the values
+/// of those whose names look like secrets.
+pub fn capture_env_vars() -> BTreeMap<String, String> {
+    let allowlist = ALLOWLIST.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    if allowlist.is_empty() {
+        return BTreeMap::new();
+    }
+    let redact: Vec<String> = match &*REDACT_PATTERNS.lock().unwrap_or_else(|e| e.into_inner()) {
+        Some(patterns) => patterns.iter().map(|p| p.to_uppercase()).collect(),
+        None => DEFAULT_REDACT_PATTERNS
+            .iter()
+            .map(|p| p.to_uppercase())
+            .collect(),
+    };
+
+    std::env::vars_os()
+        .filter_map(|(name, value)| {
+            let name = name.into_string().ok()?;
+            if !allowlist.iter().any(|p| glob_match(p, &name)) {
+                return None;
+            }
+            let upper = name.to_uppercase();
+            let value = if redact.iter().any(|p| glob_match(p, &upper)) {
+                REDACTED.to_string()
+            } else {
+                value.to_string_lossy().into_owned()
+            };
+            Some((name, value))
+        })
+        .collect()
+}
+
+/// Matches `text` against a glob `pattern` supporting `*` and `?`.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen, and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and try again
+                Some((star, star_t)) => {
+                    backtrack = Some((star, star_t + 1));
+                    p = star + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
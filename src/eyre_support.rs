@@ -0,0 +1,188 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    error::Error,
+    fmt::{self, Formatter},
+    panic::Location,
+    sync::Arc,
+};
+
+use crate::{id::ErrorId, ErrorLevel, TracebackError};
+
+/// An `eyre::EyreHandler` that keeps a `TracebackError` alongside every
+/// `eyre::Report`.
+///
+/// The error is created together with the report, so it captures the scoped context,
+/// the process context and the spans like any other `TracebackError`, plus a
+/// backtrace when `RUST_BACKTRACE` enables one. Its chain has a node for the report's
+/// error and each of its sources, at the location the report was created.
+///
+/// When the report is dropped, the error is dropped with it and goes to the
+/// configured callback, unless it was marked as handled with `ReportExt`. Reports that
+/// wrap a `TracebackError` leave the reporting to that error.
+///
+/// eyre moves the handler into the new report on `wrap_err` and `context` without
+/// showing it the added message, so an error reported on drop only has the chain the
+/// report was created with. `ReportExt::report` reports the report's current chain
+/// instead.
+pub struct TracebackHandler {
+    error: TracebackError,
+}
+
+impl TracebackHandler {
+    /// Creates the handler for a report of `error`. This is the hook `install` sets.
+    pub fn new(error: &(dyn Error + 'static)) -> Self {
+        let mut node = TracebackError::new(String::new(), String::new(), 0, ErrorLevel::Error);
+        let backtrace = Backtrace::capture();
+        if backtrace.status() == BacktraceStatus::Captured {
+            node.backtrace = Some(backtrace.to_string());
+        }
+        node.is_handled = error.is::<TracebackError>();
+        Self {
+            error: chain(node, error),
+        }
+    }
+
+    /// Returns the error that is reported when the report is dropped.
+    pub fn error(&self) -> &TracebackError {
+        &self.error
+    }
+
+    /// Returns the error that is reported when the report is dropped, to add data to
+    /// it or mark it as handled.
+    pub fn error_mut(&mut self) -> &mut TracebackError {
+        &mut self.error
+    }
+}
+
+impl eyre::EyreHandler for TracebackHandler {
+    /// Renders the report like a `TracebackError`, with a node for the report's
+    /// current error and each of its sources, followed by the backtrace if one was
+    /// captured.
+    fn debug(&self, error: &(dyn Error + 'static), f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return fmt::Debug::fmt(error, f);
+        }
+        let mut view = self.error.clone();
+        // Only a copy for rendering, which must not be reported
+        view.is_handled = true;
+        let view = chain(view, error);
+        write!(f, "{}", view)?;
+        if let Some(backtrace) = &self.error.backtrace {
+            write!(f, "\n\nBacktrace:\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+
+    fn track_caller(&mut self, location: &'static Location<'static>) {
+        let mut node = Some(&mut self.error);
+        while let Some(current) = node {
+            current.file = location.file().to_string();
+            current.line = location.line();
+            node = current.parent.as_mut().map(Arc::make_mut);
+        }
+    }
+}
+
+// Gives `node` the message of `error`, and a copy of it as parent for each source
+fn chain(mut node: TracebackError, error: &(dyn Error + 'static)) -> TracebackError {
+    node.message = error.to_string();
+    node.parent = None;
+    node.parent_id = None;
+    match error.source() {
+        Some(source) => {
+            let mut parent = node.clone();
+            parent.id = ErrorId::new();
+            let parent = chain(parent, source);
+            node.with_parent(parent)
+        }
+        None => node,
+    }
+}
+
+/// Installs `TracebackHandler` as the handler of every `eyre::Report` created from
+/// now on.
+///
+/// Fails if a handler was already installed, which eyre only allows once per process.
+///
+/// ```rust
+/// use traceback_error::eyre_support::ReportExt;
+///
+/// traceback_error::eyre_support::install().unwrap();
+///
+/// let mut report = eyre::eyre!("Connection refused");
+/// assert_eq!(report.traceback().unwrap().message, "Connection refused");
+///
+/// // Without this, the error would be reported when the report is dropped
+/// report.mark_handled();
+/// ```
+pub fn install() -> Result<(), eyre::InstallError> {
+    eyre::set_hook(Box::new(|error| Box::new(TracebackHandler::new(error))))
+}
+
+/// Gives access to the `TracebackError` of reports created with `TracebackHandler`
+/// installed.
+pub trait ReportExt {
+    /// Returns the error kept by the report's handler, or `None` if the report has
+    /// another kind of handler.
+    fn traceback(&self) -> Option<&TracebackError>;
+
+    /// Returns the error kept by the report's handler mutably, or `None` if the report
+    /// has another kind of handler.
+    fn traceback_mut(&mut self) -> Option<&mut TracebackError>;
+
+    /// Marks the report's error as handled, so it is not reported when the report is
+    /// dropped.
+    fn mark_handled(&mut self);
+
+    /// Reports the report's error right away, with a node for the report's current
+    /// error and each of its sources, including the messages added with `wrap_err`.
+    ///
+    /// Does nothing for errors marked as handled, or reports with another kind of
+    /// handler.
+    ///
+    /// ```rust
+    /// use eyre::WrapErr;
+    /// use traceback_error::{eyre_support::ReportExt, testing};
+    ///
+    /// traceback_error::eyre_support::install().unwrap();
+    /// let capture = testing::capture();
+    ///
+    /// let result: eyre::Result<()> = Err(eyre::eyre!("disk full")).wrap_err("saving config");
+    /// result.unwrap_err().report();
+    ///
+    /// let errors = capture.take();
+    /// assert_eq!(errors.len(), 1);
+    /// assert_eq!(errors[0].message, "saving config");
+    /// assert_eq!(errors[0].parent.as_ref().unwrap().message, "disk full");
+    /// ```
+    fn report(self);
+}
+
+impl ReportExt for eyre::Report {
+    fn traceback(&self) -> Option<&TracebackError> {
+        self.handler()
+            .downcast_ref::<TracebackHandler>()
+            .map(TracebackHandler::error)
+    }
+
+    fn traceback_mut(&mut self) -> Option<&mut TracebackError> {
+        self.handler_mut()
+            .downcast_mut::<TracebackHandler>()
+            .map(TracebackHandler::error_mut)
+    }
+
+    fn mark_handled(&mut self) {
+        if let Some(error) = self.traceback_mut() {
+            error.is_handled = true;
+        }
+    }
+
+    fn report(mut self) {
+        // Leaves a default error behind, which is never reported
+        let node = match self.traceback_mut() {
+            Some(error) if !error.is_handled => std::mem::take(error),
+            _ => return,
+        };
+        chain(node, self.as_ref()).report();
+    }
+}
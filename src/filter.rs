@@ -0,0 +1,224 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+    sync::{Mutex, Once},
+};
+
+use crate::{level::registered_severity, ErrorLevel};
+
+/// The environment variable the level filter is read from, the first time an error
+/// is handled.
+///
+/// ```rust
+/// use traceback_error::{assert_no_errors, assert_reported, testing, traceback, ErrorLevel};
+///
+/// std::env::set_var(traceback_error::filter::LEVEL_ENV_VAR, "warn");
+/// let _capture = testing::capture();
+///
+/// let mut error = traceback!("Cache miss");
+/// error.level = ErrorLevel::Debug;
+/// error.report();
+/// assert_no_errors!();
+///
+/// let mut error = traceback!("Slow query");
+/// error.level = ErrorLevel::Warn;
+/// error.report();
+/// assert_reported!(level = Warn, message = "Slow query");
+/// ```
+pub const LEVEL_ENV_VAR: &str = "TRACEBACK_LEVEL";
+
+static FILTER: Mutex<Option<LevelFilter>> = Mutex::new(None);
+static ENV_LOADED: Once = Once::new();
+
+/// The least severe level a directive lets through, or `Off` to let nothing through.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Threshold {
+    Off,
+    AtLeast(ErrorLevel),
+}
+
+impl Threshold {
+    fn allows(&self, level: &ErrorLevel) -> bool {
+        match self {
+            Threshold::Off => false,
+            Threshold::AtLeast(min) => level >= min,
+        }
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => return Ok(Threshold::Off),
+            "all" => return Ok(Threshold::AtLeast(ErrorLevel::None)),
+            _ => {}
+        }
+        match s.parse()? {
+            // Catch typos, rather than treating them as levels of the default severity
+            ErrorLevel::Other(name) if registered_severity(&name).is_none() => {
+                Err(format!("unknown level {:?}", name))
+            }
+            level => Ok(Threshold::AtLeast(level)),
+        }
+    }
+}
+
+impl Display for Threshold {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Threshold::Off => write!(f, "off"),
+            Threshold::AtLeast(level) => write!(f, "{}", level),
+        }
+    }
+}
+
+/// Decides which errors are reported, based on the module they were created in and
+/// their level, in the style of `RUST_LOG`.
+///
+/// A filter is a comma separated list of directives. A directive is either a bare
+/// level, which sets the default, or `module=level`, which applies to that module and
+/// all modules below it. The most specific matching module wins. Errors that do not
+/// pass the filter are silently marked as handled.
+///
+/// ```rust
+/// use traceback_error::{filter::LevelFilter, ErrorLevel};
+///
+/// let filter: LevelFilter = "warn,my_crate::db=debug,noisy_dep=off".parse().unwrap();
+/// assert!(filter.enabled(Some("my_crate::db::pool"), &ErrorLevel::Debug));
+/// assert!(!filter.enabled(Some("my_crate::http"), &ErrorLevel::Debug));
+/// assert!(filter.enabled(Some("my_crate::http"), &ErrorLevel::Error));
+/// assert!(!filter.enabled(Some("noisy_dep"), &ErrorLevel::Error));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelFilter {
+    default: Option<Threshold>,
+    directives: Vec<(String, Threshold)>,
+}
+
+impl LevelFilter {
+    /// Returns whether an error created in `module_path` with the given level passes.
+    ///
+    /// Errors without a module path, and errors no directive matches, fall back to
+    /// the default directive. Without one, they are reported.
+    pub fn enabled(&self, module_path: Option<&str>, level: &ErrorLevel) -> bool {
+        let directive = module_path.and_then(|module| {
+            self.directives
+                .iter()
+                .filter(|(target, _)| module_matches(target, module))
+                .max_by_key(|(target, _)| target.len())
+                .map(|(_, threshold)| threshold)
+        });
+        match directive.or(self.default.as_ref()) {
+            Some(threshold) => threshold.allows(level),
+            None => true,
+        }
+    }
+}
+
+impl FromStr for LevelFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = LevelFilter {
+            default: None,
+            directives: Vec::new(),
+        };
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, threshold)) => {
+                    let target = target.trim();
+                    if target.is_empty() {
+                        return Err(format!("missing module in directive {:?}", directive));
+                    }
+                    filter
+                        .directives
+                        .push((target.to_string(), threshold.parse()?));
+                }
+                None => filter.default = Some(directive.parse()?),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl Display for LevelFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut directives: Vec<String> = Vec::new();
+        if let Some(default) = &self.default {
+            directives.push(default.to_string());
+        }
+        for (target, threshold) in &self.directives {
+            directives.push(format!("{}={}", target, threshold));
+        }
+        write!(f, "{}", directives.join(","))
+    }
+}
+
+// `my_crate::db` matches `my_crate::db` and `my_crate::db::pool`, but not `my_crate::dbx`
+fn module_matches(target: &str, module: &str) -> bool {
+    match module.strip_prefix(target) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+/// Sets the level filter at runtime, replacing the one read from `TRACEBACK_LEVEL`.
+///
+/// ```rust
+/// use traceback_error::{assert_reported, filter, testing, traceback, ErrorLevel};
+///
+/// filter::set_level_filter("warn,my_crate::jobs=error").unwrap();
+/// let capture = testing::capture();
+///
+/// let mut error = traceback!("Cache miss");
+/// error.level = ErrorLevel::Debug;
+/// error.report();
+/// let mut error = traceback!("Slow query");
+/// error.level = ErrorLevel::Warn;
+/// error.report();
+///
+/// assert_reported!(level = Warn, message = "Slow query");
+/// assert_eq!(capture.errors().len(), 1);
+/// ```
+pub fn set_level_filter(spec: &str) -> Result<(), String> {
+    let filter: LevelFilter = spec.parse()?;
+    // A filter set at runtime takes precedence over the environment
+    ENV_LOADED.call_once(|| {});
+    let mut current = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    *current = Some(filter);
+    Ok(())
+}
+
+/// Removes the level filter, so every error is reported again.
+pub fn clear_level_filter() {
+    ENV_LOADED.call_once(|| {});
+    let mut current = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    *current = None;
+}
+
+/// Returns whether an error created in `module_path` with the given level would be
+/// reported under the current filter.
+pub fn is_enabled(module_path: Option<&str>, level: &ErrorLevel) -> bool {
+    ENV_LOADED.call_once(load_from_env);
+    let current = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    match current.as_ref() {
+        Some(filter) => filter.enabled(module_path, level),
+        None => true,
+    }
+}
+
+fn load_from_env() {
+    let spec = match std::env::var(LEVEL_ENV_VAR) {
+        Ok(spec) => spec,
+        Err(_) => return,
+    };
+    match spec.parse::<LevelFilter>() {
+        Ok(filter) => {
+            let mut current = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+            *current = Some(filter);
+        }
+        Err(e) => println!("Ignoring invalid {}: {}", LEVEL_ENV_VAR, e),
+    }
+}
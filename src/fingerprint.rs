@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    fmt::{Display, Formatter},
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use crate::TracebackError;
+
+type FingerprintKeyFn = Arc<dyn Fn(&TracebackError) -> Vec<String> + Send + Sync>;
+
+static KEY_FN: Mutex<Option<FingerprintKeyFn>> = Mutex::new(None);
+
+/// A stable hash identifying "the same" error across runs and machines.
+///
+/// By default it is computed from the file, line, message template and level of every
+/// error in the chain. Message templates have every quoted string and every word
+/// containing a digit replaced with `<*>`, so `"user 42 not found"` and
+/// `"user 7 not found"` share a fingerprint. The creation time, ids, `extra_data` and
+/// `metadata` are ignored.
+///
+/// ```rust
+/// use traceback_error::traceback;
+///
+/// let mut a = traceback!("timed out after 30s");
+/// let mut b = traceback!("timed out after 45s");
+/// // Different lines give different fingerprints
+/// assert_ne!(a.fingerprint(), b.fingerprint());
+///
+/// let errors: Vec<_> = (0..2).map(|i| traceback!(format!("retry {} failed", i))).collect();
+/// assert_eq!(errors[0].fingerprint(), errors[1].fingerprint());
+/// # a.is_handled = true;
+/// # b.is_handled = true;
+/// # for mut e in errors { e.is_handled = true; }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Fingerprint(u64);
+
+impl Fingerprint {
+    /// Computes a fingerprint from a list of key components.
+    pub fn from_components<I, S>(components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        // FNV-1a, which unlike the standard library's hasher is stable across releases
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0100_0000_01b3;
+        let mut hash = OFFSET;
+        for component in components {
+            for byte in component.as_ref().bytes().chain(std::iter::once(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(PRIME);
+            }
+        }
+        Self(hash)
+    }
+}
+
+impl Display for Fingerprint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for Fingerprint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16)
+            .map(Self)
+            .map_err(|e| format!("invalid fingerprint {:?}: {}", s, e))
+    }
+}
+
+impl From<Fingerprint> for String {
+    fn from(fingerprint: Fingerprint) -> Self {
+        fingerprint.to_string()
+    }
+}
+
+impl TryFrom<String> for Fingerprint {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Replaces the function computing the key components an error is fingerprinted by.
+///
+/// The function receives the outermost error of a chain. Use `default_key` inside it
+/// to extend the default components rather than replace them.
+///
+/// ```rust
+/// use traceback_error::fingerprint::{default_key, set_fingerprint_key};
+///
+/// // Also tell errors apart by the tenant they happened for
+/// set_fingerprint_key(|error| {
+///     let mut key = default_key(error);
+///     if let Some(tenant) = error.extra_data.get("tenant") {
+///         key.push(tenant.to_string());
+///     }
+///     key
+/// });
+/// ```
+pub fn set_fingerprint_key<F>(key_fn: F)
+where
+    F: Fn(&TracebackError) -> Vec<String> + Send + Sync + 'static,
+{
+    let mut key = KEY_FN.lock().unwrap_or_else(|e| e.into_inner());
+    *key = Some(Arc::new(key_fn));
+}
+
+/// Restores the default fingerprint key.
+pub fn reset_fingerprint_key() {
+    let mut key = KEY_FN.lock().unwrap_or_else(|e| e.into_inner());
+    *key = None;
+}
+
+/// The default key components: file, line, message template and level of every
+/// error in the chain, from the outermost one to the root cause. The causes of an
+/// aggregate error come right after it, in order.
+pub fn default_key(error: &TracebackError) -> Vec<String> {
+    let mut components = Vec::new();
+    push_key(error, &mut components);
+    components
+}
+
+fn push_key(error: &TracebackError, components: &mut Vec<String>) {
+    let mut node = Some(error);
+    while let Some(e) = node {
+        components.push(e.file.clone());
+        components.push(e.line.to_string());
+        components.push(message_template(&e.message));
+        components.push(format!("{:?}", e.level));
+        for cause in &e.causes {
+            push_key(cause, components);
+        }
+        node = e.parent.as_deref();
+    }
+}
+
+/// Computes the fingerprint of an error with the configured key function.
+pub fn compute(error: &TracebackError) -> Fingerprint {
+    let key_fn = KEY_FN.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let components = match key_fn {
+        Some(key_fn) => key_fn(error),
+        None => default_key(error),
+    };
+    Fingerprint::from_components(components)
+}
+
+/// Replaces quoted strings and words containing digits with `<*>`, so messages that
+/// only differ in the values they mention share a template.
+///
+/// ```rust
+/// use traceback_error::fingerprint::message_template;
+///
+/// assert_eq!(
+///     message_template("user 42 not found in 'users', can't retry"),
+///     "user <*> not found in <*>, can't retry"
+/// );
+/// assert_eq!(
+///     message_template("no key \"clé\" in `config.toml`, unclosed \"quote"),
+///     "no key <*> in <*>, unclosed \"quote"
+/// );
+/// ```
+pub fn message_template(message: &str) -> String {
+    let mut template = String::with_capacity(message.len());
+    let mut chars = message.char_indices().peekable();
+    let mut prev = ' ';
+    while let Some((i, c)) = chars.next() {
+        // An apostrophe inside a word, like in "can't", does not start a quote
+        let starts_quote = c == '"' || c == '`' || (c == '\'' && !prev.is_alphanumeric());
+        prev = c;
+        if starts_quote {
+            // Skip to the closing quote, or keep the quote if there is none
+            let after = i + c.len_utf8();
+            if let Some(end) = message[after..].find(c) {
+                template.push_str("<*>");
+                let close = after + end;
+                while chars.next_if(|&(j, _)| j <= close).is_some() {}
+                continue;
+            }
+            template.push(c);
+        } else if is_word_char(c) {
+            let mut word = c.to_string();
+            while let Some(&(_, next)) = chars.peek() {
+                if !is_word_char(next) {
+                    break;
+                }
+                word.push(next);
+                prev = next;
+                chars.next();
+            }
+            if word.chars().any(|c| c.is_ascii_digit()) {
+                template.push_str("<*>");
+            } else {
+                template.push_str(&word);
+            }
+        } else {
+            template.push(c);
+        }
+    }
+    template
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Wraps a `TracebackError` so it is hashed and compared by its fingerprint, which
+/// lets identical failures be grouped in a `HashMap` or `HashSet`.
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use traceback_error::{fingerprint::ByFingerprint, traceback};
+///
+/// let mut groups: HashMap<ByFingerprint, usize> = HashMap::new();
+/// for i in 0..3 {
+///     let mut error = traceback!(format!("request {} failed", i));
+///     error.is_handled = true;
+///     *groups.entry(ByFingerprint(error)).or_default() += 1;
+/// }
+/// assert_eq!(groups.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ByFingerprint(pub TracebackError);
+
+impl PartialEq for ByFingerprint {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.fingerprint() == other.0.fingerprint()
+    }
+}
+
+impl Eq for ByFingerprint {}
+
+impl Hash for ByFingerprint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.fingerprint().hash(state);
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    fmt::{Display, Formatter},
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Crockford's base32 alphabet, which leaves out I, L, O and U to avoid confusion
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ENCODED_LEN: usize = 26;
+const REFERENCE_LEN: usize = 6;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A unique identifier for a `TracebackError`, in the ULID format.
+///
+/// The first 48 bits hold the creation time in milliseconds, so ids sort by creation
+/// time, and the remaining 80 bits are random. An id is displayed and serialized as
+/// a 26 character Crockford base32 string.
+///
+/// ```rust
+/// use traceback_error::id::ErrorId;
+///
+/// let id = ErrorId::new();
+/// let parsed: ErrorId = id.to_string().parse().unwrap();
+/// assert_eq!(id, parsed);
+/// assert!(id.reference().starts_with("ERR-"));
+/// ```
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize,
+)]
+#[serde(into = "String", try_from = "String")]
+pub struct ErrorId(u128);
+
+impl ErrorId {
+    /// Generates a new id from the current time and 80 random bits.
+    pub fn new() -> Self {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let random = ((random_u64() as u128) << 16) | (random_u64() as u128 & 0xFFFF);
+        Self((millis & 0xFFFF_FFFF_FFFF) << 80 | random)
+    }
+
+    /// The all-zero id, used for errors that were never given one.
+    pub const fn nil() -> Self {
+        Self(0)
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns a short, human-readable reference like `ERR-7F3K9Q`, made of the last
+    /// characters of the id.
+    ///
+    /// References are meant to be shown to end users and are short enough to read out
+    /// loud. They are not guaranteed to be unique, but the random part of the id makes
+    /// collisions unlikely.
+    pub fn reference(&self) -> String {
+        let encoded = self.to_string();
+        format!("ERR-{}", &encoded[ENCODED_LEN - REFERENCE_LEN..])
+    }
+
+    /// Returns the suffix an id must end with to match the given reference, accepting
+    /// the reference with or without its `ERR-` prefix and in any case.
+    pub fn reference_suffix(reference: &str) -> String {
+        let reference = reference.trim().to_uppercase();
+        match reference.strip_prefix("ERR-") {
+            Some(suffix) => suffix.to_string(),
+            None => reference,
+        }
+    }
+}
+
+impl Display for ErrorId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut buf = [0u8; ENCODED_LEN];
+        let mut value = self.0;
+        for slot in buf.iter_mut().rev() {
+            *slot = ALPHABET[(value & 0x1F) as usize];
+            value >>= 5;
+        }
+        // The alphabet is ASCII, so this cannot fail
+        write!(f, "{}", std::str::from_utf8(&buf).unwrap())
+    }
+}
+
+impl FromStr for ErrorId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != ENCODED_LEN {
+            return Err(format!(
+                "an error id must be {} characters long, got {}",
+                ENCODED_LEN,
+                s.len()
+            ));
+        }
+        let mut value: u128 = 0;
+        for (i, c) in s.bytes().enumerate() {
+            let digit = ALPHABET
+                .iter()
+                .position(|&a| a == c.to_ascii_uppercase())
+                .ok_or_else(|| format!("invalid character {:?} in error id", c as char))?;
+            // 26 characters hold 130 bits, so the first one may only use 3 of its 5
+            if i == 0 && digit > 7 {
+                return Err("error id is out of range".to_string());
+            }
+            value = (value << 5) | digit as u128;
+        }
+        Ok(Self(value))
+    }
+}
+
+impl From<ErrorId> for String {
+    fn from(id: ErrorId) -> Self {
+        id.to_string()
+    }
+}
+
+impl TryFrom<String> for ErrorId {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Returns 64 random bits from the standard library's hasher keys.
+///
+/// This is not suitable for cryptography, but it is enough to keep ids unique
+/// without pulling in a random number generator.
+pub(crate) fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish()
+}
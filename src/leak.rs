@@ -0,0 +1,172 @@
+use std::{backtrace::Backtrace, cell::RefCell, fmt};
+
+use crate::TracebackError;
+
+thread_local! {
+    // One list of leaks per live guard on this thread, innermost last
+    static TRACKED: RefCell<Vec<Vec<LeakedError>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// An error that was dropped while unhandled during leak tracking.
+///
+/// `error` is the leaked error, marked as handled. Its `file` and `line` are where it was
+/// created, or converted for `#[track_caller]` conversions such as the `Traceback`
+/// derive. `dropped_at` is the `file:line:column` of the first frame outside of this
+/// crate and the standard library when the error was dropped, if debug info is
+/// available.
+#[derive(Debug)]
+pub struct LeakedError {
+    pub error: TracebackError,
+    pub dropped_at: Option<String>,
+}
+
+impl fmt::Display for LeakedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) created at {}:{}",
+            self.error.message, self.error.level, self.error.file, self.error.line
+        )?;
+        if let Some(dropped_at) = &self.dropped_at {
+            write!(f, ", dropped at {}", dropped_at)?;
+        }
+        Ok(())
+    }
+}
+
+/// Starts tracking leaked errors on the current thread, until the returned guard is
+/// dropped.
+///
+/// While the guard is alive, errors dropped on this thread without being reported or
+/// handled are collected instead of being handed to the callback, so tests don't leave
+/// files in the `errors` directory. When the guard is dropped, it panics with a summary
+/// of the leaked errors, failing the test. Use `print_only` to print the summary
+/// instead, which suits a guard kept for the whole of `main`.
+///
+/// Leaks are only tracked in builds with debug assertions. In release builds, the
+/// guard does nothing and dropped errors are reported as usual.
+///
+/// ```rust
+/// use traceback_error::{leak, testing, traceback};
+///
+/// let capture = testing::capture();
+/// let guard = leak::track();
+/// traceback!("Reported").report();
+/// traceback!("Swallowed").handled(Some("expected in tests"));
+/// drop(traceback!("Forgotten"));
+///
+/// let leaks = guard.finish();
+/// assert_eq!(leaks.len(), 1);
+/// assert_eq!(leaks[0].error.message, "Forgotten");
+/// assert_eq!(capture.take()[0].message, "Reported");
+/// ```
+///
+/// ```rust,should_panic
+/// let _guard = traceback_error::leak::track();
+/// let _ = traceback_error::traceback!("Forgotten");
+/// // Panics here: 1 unhandled error leaked
+/// ```
+pub fn track() -> LeakGuard {
+    TRACKED.with(|tracked| tracked.borrow_mut().push(Vec::new()));
+    LeakGuard { print_only: false }
+}
+
+/// Tracks leaked errors on the current thread while alive, see `track`.
+#[must_use = "leaks are only tracked while the guard is alive"]
+pub struct LeakGuard {
+    print_only: bool,
+}
+
+impl LeakGuard {
+    /// Prints the summary of leaked errors to stderr when dropped, instead of panicking.
+    pub fn print_only(mut self) -> Self {
+        self.print_only = true;
+        self
+    }
+
+    /// Stops tracking, and returns the errors leaked so far without reporting them.
+    pub fn finish(self) -> Vec<LeakedError> {
+        let leaks = take_leaks();
+        std::mem::forget(self);
+        leaks
+    }
+}
+
+impl Drop for LeakGuard {
+    fn drop(&mut self) {
+        let leaks = take_leaks();
+        if leaks.is_empty() {
+            return;
+        }
+        let summary = summarize(&leaks);
+        if self.print_only || std::thread::panicking() {
+            eprintln!("{}", summary);
+        } else {
+            panic!("{}", summary);
+        }
+    }
+}
+
+fn take_leaks() -> Vec<LeakedError> {
+    TRACKED.with(|tracked| tracked.borrow_mut().pop().unwrap_or_default())
+}
+
+fn summarize(leaks: &[LeakedError]) -> String {
+    let mut summary = format!("{} unhandled error(s) leaked:", leaks.len());
+    for leak in leaks {
+        summary.push_str(&format!("\n  - {}", leak));
+    }
+    summary
+}
+
+/// Records an error dropped while unhandled if leaks are being tracked on this thread,
+/// and returns whether it was recorded.
+pub(crate) fn record(error: &mut TracebackError) -> bool {
+    if !cfg!(debug_assertions) {
+        return false;
+    }
+    TRACKED.with(|tracked| {
+        // Already borrowed if a leak is dropped while recording, which never happens
+        let mut tracked = match tracked.try_borrow_mut() {
+            Ok(tracked) => tracked,
+            Err(_) => return false,
+        };
+        let leaks = match tracked.last_mut() {
+            Some(leaks) => leaks,
+            None => return false,
+        };
+        let mut error = std::mem::take(error);
+        error.is_handled = true;
+        leaks.push(LeakedError {
+            error,
+            dropped_at: drop_location(),
+        });
+        true
+    })
+}
+
+// Finds the first frame outside of this crate and the standard library in a backtrace
+// of the drop, which is where the error went out of scope.
+fn drop_location() -> Option<String> {
+    let backtrace = Backtrace::force_capture().to_string();
+    let mut skip = true;
+    for line in backtrace.lines().map(str::trim) {
+        match line.strip_prefix("at ") {
+            Some(location) if !skip => return Some(location.to_string()),
+            Some(_) => {}
+            None => {
+                let symbol = line.split_once(": ").map_or(line, |(_, symbol)| symbol);
+                skip = [
+                    "std::",
+                    "core::",
+                    "alloc::",
+                    "traceback_error::",
+                    "<traceback_error::",
+                ]
+                .iter()
+                .any(|prefix| symbol.starts_with(prefix));
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    str::FromStr,
+    sync::Mutex,
+};
+
+// Severities of `Other` levels registered with `set_level_severity`
+static OTHER_SEVERITIES: Mutex<Option<HashMap<String, u8>>> = Mutex::new(None);
+
+/// How severe a `TracebackError` is.
+///
+/// Levels are ordered by their numeric `severity`, so they can be compared and used as
+/// thresholds. They parse from and display as lowercase names.
+///
+/// ```rust
+/// use traceback_error::ErrorLevel;
+///
+/// let level: ErrorLevel = "warn".parse().unwrap();
+/// assert_eq!(level, ErrorLevel::Warn);
+/// assert!(ErrorLevel::Error >= level);
+/// assert!(ErrorLevel::Debug < level);
+/// assert_eq!(ErrorLevel::Critical.to_string(), "critical");
+/// assert_eq!(ErrorLevel::Error.syslog_severity(), 3);
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorLevel {
+    None,
+    Unknown,
+    Log,
+    Debug,
+    Warn,
+    Error,
+    Critical,
+    Fatal,
+    Other(String),
+}
+
+impl ErrorLevel {
+    /// The severity `Unknown` levels, and `Other` levels without a registered
+    /// severity, have. It sits above `Warn` so errors created without a level are not
+    /// silenced by a warn threshold.
+    pub const DEFAULT_SEVERITY: u8 = 35;
+
+    /// Returns the numeric severity of the level, where higher is more severe.
+    ///
+    /// | Level      | Severity |
+    /// |------------|----------|
+    /// | `None`     | 0        |
+    /// | `Debug`    | 10       |
+    /// | `Log`      | 20       |
+    /// | `Warn`     | 30       |
+    /// | `Unknown`  | 35       |
+    /// | `Error`    | 40       |
+    /// | `Critical` | 50       |
+    /// | `Fatal`    | 60       |
+    ///
+    /// `Other` levels have the severity registered with `set_level_severity`, or 35.
+    pub fn severity(&self) -> u8 {
+        match self {
+            ErrorLevel::None => 0,
+            ErrorLevel::Debug => 10,
+            ErrorLevel::Log => 20,
+            ErrorLevel::Warn => 30,
+            ErrorLevel::Unknown => Self::DEFAULT_SEVERITY,
+            ErrorLevel::Error => 40,
+            ErrorLevel::Critical => 50,
+            ErrorLevel::Fatal => 60,
+            ErrorLevel::Other(name) => registered_severity(name).unwrap_or(Self::DEFAULT_SEVERITY),
+        }
+    }
+
+    /// Maps the level to a syslog severity, from 1 (alert) to 7 (debug).
+    pub fn syslog_severity(&self) -> u8 {
+        match self.severity() {
+            60..=u8::MAX => 1,
+            50..=59 => 2,
+            35..=49 => 3,
+            30..=34 => 4,
+            20..=29 => 6,
+            _ => 7,
+        }
+    }
+
+    // Breaks ties between levels of the same severity, so that the ordering agrees
+    // with equality
+    fn variant_index(&self) -> u8 {
+        match self {
+            ErrorLevel::None => 0,
+            ErrorLevel::Unknown => 1,
+            ErrorLevel::Log => 2,
+            ErrorLevel::Debug => 3,
+            ErrorLevel::Warn => 4,
+            ErrorLevel::Error => 5,
+            ErrorLevel::Critical => 6,
+            ErrorLevel::Fatal => 7,
+            ErrorLevel::Other(_) => 8,
+        }
+    }
+}
+
+/// Sets the severity of the `Other` level with the given name.
+///
+/// Changing a severity changes how levels order, so it should be done at startup,
+/// before levels are stored in ordered collections.
+///
+/// ```rust
+/// use traceback_error::{level::set_level_severity, ErrorLevel};
+///
+/// set_level_severity("audit", 45);
+/// assert!(ErrorLevel::Other("audit".to_string()) > ErrorLevel::Error);
+/// ```
+pub fn set_level_severity(name: &str, severity: u8) {
+    let mut severities = OTHER_SEVERITIES.lock().unwrap_or_else(|e| e.into_inner());
+    severities
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), severity);
+}
+
+/// Returns the severity registered for the `Other` level with the given name.
+pub fn registered_severity(name: &str) -> Option<u8> {
+    let severities = OTHER_SEVERITIES.lock().unwrap_or_else(|e| e.into_inner());
+    severities.as_ref()?.get(name).copied()
+}
+
+impl Ord for ErrorLevel {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.severity()
+            .cmp(&other.severity())
+            .then_with(|| self.variant_index().cmp(&other.variant_index()))
+            .then_with(|| match (self, other) {
+                (ErrorLevel::Other(a), ErrorLevel::Other(b)) => a.cmp(b),
+                _ => Ordering::Equal,
+            })
+    }
+}
+
+impl PartialOrd for ErrorLevel {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for ErrorLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ErrorLevel::None => "none",
+            ErrorLevel::Unknown => "unknown",
+            ErrorLevel::Log => "log",
+            ErrorLevel::Debug => "debug",
+            ErrorLevel::Warn => "warn",
+            ErrorLevel::Error => "error",
+            ErrorLevel::Critical => "critical",
+            ErrorLevel::Fatal => "fatal",
+            ErrorLevel::Other(name) => name,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses a level name, ignoring case. `info` and `trace` are accepted as aliases for
+/// `log` and `debug`. Any other non-empty name parses as an `Other` level.
+impl FromStr for ErrorLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let level = match s.to_lowercase().as_str() {
+            "" => return Err("level name is empty".to_string()),
+            "none" => ErrorLevel::None,
+            "unknown" => ErrorLevel::Unknown,
+            "log" | "info" => ErrorLevel::Log,
+            "debug" | "trace" => ErrorLevel::Debug,
+            "warn" | "warning" => ErrorLevel::Warn,
+            "error" => ErrorLevel::Error,
+            "critical" | "crit" => ErrorLevel::Critical,
+            "fatal" => ErrorLevel::Fatal,
+            _ => ErrorLevel::Other(s.to_string()),
+        };
+        Ok(level)
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<log::Level> for ErrorLevel {
+    fn from(level: log::Level) -> Self {
+        match level {
+            log::Level::Error => ErrorLevel::Error,
+            log::Level::Warn => ErrorLevel::Warn,
+            log::Level::Info => ErrorLevel::Log,
+            log::Level::Debug | log::Level::Trace => ErrorLevel::Debug,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<&ErrorLevel> for log::Level {
+    fn from(level: &ErrorLevel) -> Self {
+        match level.severity() {
+            35..=u8::MAX => log::Level::Error,
+            30..=34 => log::Level::Warn,
+            20..=29 => log::Level::Info,
+            10..=19 => log::Level::Debug,
+            _ => log::Level::Trace,
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<ErrorLevel> for log::Level {
+    fn from(level: ErrorLevel) -> Self {
+        (&level).into()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<tracing::Level> for ErrorLevel {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR => ErrorLevel::Error,
+            tracing::Level::WARN => ErrorLevel::Warn,
+            tracing::Level::INFO => ErrorLevel::Log,
+            _ => ErrorLevel::Debug,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<&ErrorLevel> for tracing::Level {
+    fn from(level: &ErrorLevel) -> Self {
+        match level.severity() {
+            35..=u8::MAX => tracing::Level::ERROR,
+            30..=34 => tracing::Level::WARN,
+            20..=29 => tracing::Level::INFO,
+            10..=19 => tracing::Level::DEBUG,
+            _ => tracing::Level::TRACE,
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl From<ErrorLevel> for tracing::Level {
+    fn from(level: ErrorLevel) -> Self {
+        (&level).into()
+    }
+}
@@ -1,20 +1,223 @@
+#[cfg(feature = "anyhow")]
+mod anyhow_support;
 pub mod block_on;
+pub mod breadcrumbs;
+pub mod context;
+pub mod dedup;
+pub mod diagnostic;
+mod dispatch;
+pub mod enrich;
+pub mod env_capture;
+#[cfg(feature = "eyre")]
+pub mod eyre_support;
+pub mod filter;
+pub mod fingerprint;
+pub mod id;
+pub mod leak;
+pub mod level;
+pub mod lifecycle;
+#[cfg(feature = "log")]
+pub mod log_support;
+pub mod merge;
+pub mod process_context;
+pub mod rate_limit;
+pub mod resources;
 pub mod set_callback;
+pub mod span_trace;
+pub mod testing;
+mod timer;
+#[cfg(feature = "tracing")]
+pub mod tracing_support;
 
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use breadcrumbs::Breadcrumb;
+use chrono::{DateTime, TimeZone, Utc};
+use diagnostic::Diagnostic;
+use fingerprint::Fingerprint;
+use id::ErrorId;
+use merge::MergePolicy;
+use process_context::ProcessContext;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use set_callback::TracebackCallbackType;
+use span_trace::SpanInfo;
 use std::{
+    collections::BTreeMap,
     error::Error,
     fmt::{Display, Formatter},
     fs::File,
     io::Write,
+    path::PathBuf,
+    sync::Arc,
 };
 
+pub use level::ErrorLevel;
 pub use paste;
 pub use serde_json;
 
+/// Wraps every error a function returns in a new chain node, with the `derive`
+/// feature.
+///
+/// Works on functions and async functions returning `Result<_, TracebackError>`. The
+/// node's message is `<function> failed`, its level that of the wrapped error, and its
+/// extra data holds the function name under `function` and the selected arguments.
+/// The arguments are recorded when the function is called, so they have to implement
+/// `Serialize`. An argument that fails to serialize, like a map with non-string keys,
+/// is recorded as a `failed to serialize: ...` message instead.
+///
+/// The attribute takes these options:
+///
+/// - `fields(...)`: The arguments to record, like `fields(path)`, or `name = expression`
+///   pairs, like `fields(user = user.id)`.
+/// - `message`: A message to use instead of `<function> failed`.
+/// - `level`: A level name, like `warn`, to use instead of the wrapped error's level.
+///   It has to name one of the `ErrorLevel` variants.
+/// - `custom_level`: A name for an `ErrorLevel::Other` level, instead of `level`.
+///
+/// ```rust
+/// use traceback_error::{traceback, TracebackError};
+///
+/// #[traceback_error::instrument(fields(path), message = "while loading config")]
+/// fn load_config(path: &str, retries: u32) -> Result<String, TracebackError> {
+///     Err(traceback!("file not found"))
+/// }
+///
+/// let mut error = load_config("/etc/app.toml", 3).unwrap_err();
+/// assert_eq!(error.message, "while loading config");
+/// assert_eq!(error.extra_data["function"], "load_config");
+/// assert_eq!(error.extra_data["path"], "/etc/app.toml");
+/// assert_eq!(error.parent.as_ref().unwrap().message, "file not found");
+/// error.is_handled = true;
+/// ```
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use traceback_error::{traceback, TracebackError};
+///
+/// #[traceback_error::instrument(fields(scores))]
+/// fn rank(scores: &HashMap<(u32, u32), u32>) -> Result<(), TracebackError> {
+///     Err(traceback!("no scores"))
+/// }
+///
+/// let mut error = rank(&HashMap::from([((1, 2), 3)])).unwrap_err();
+/// let scores = error.extra_data["scores"].as_str().unwrap();
+/// assert!(scores.starts_with("failed to serialize: "));
+/// error.is_handled = true;
+/// ```
+///
+/// Misspelled level names are rejected when compiling:
+///
+/// ```compile_fail
+/// #[traceback_error::instrument(level = "critcal")]
+/// fn connect() -> Result<(), traceback_error::TracebackError> {
+///     Ok(())
+/// }
+/// ```
+#[cfg(feature = "derive")]
+pub use traceback_error_macros::instrument;
+/// Derives `From<T> for TracebackError` for an error enum or struct, with the
+/// `derive` feature.
+///
+/// The conversion captures the location it happens at, such as a `?`, and the module
+/// the type is defined in. Every field is copied into `extra_data` under its name, or
+/// its index for tuple variants, and the original value is kept as the error's
+/// `source`, so it can be recovered with `downcast_ref`.
+///
+/// The `traceback` attribute on the type sets defaults for all variants, and on a
+/// variant overrides them:
+///
+/// - `level`: The level name, like `warn`, which has to name one of the `ErrorLevel`
+///   variants. Defaults to `error`.
+/// - `custom_level`: A name for an `ErrorLevel::Other` level, instead of `level`.
+/// - `code`: A code stored as the code of the error's `diagnostic`.
+/// - `message`: A format string that can use the fields by name, or by index for tuple
+///   variants. Defaults to the type's `Display` output.
+///
+/// Fields have to implement `Serialize`, unless marked `#[traceback(debug)]` to be
+/// stored in their `Debug` form, or `#[traceback(skip)]` to be left out. A field that
+/// fails to serialize is stored as a `failed to serialize: ...` message instead.
+///
+/// ```rust
+/// use traceback_error::{Traceback, TracebackError};
+///
+/// #[derive(Debug, Traceback)]
+/// #[traceback(code = "DB000")]
+/// enum DbError {
+///     #[traceback(level = "warn", code = "DB001", message = "user {id} not found")]
+///     NotFound { id: u64 },
+///     #[traceback(message = "query failed: {0}")]
+///     Query(String, #[traceback(skip)] u32),
+/// }
+///
+/// impl std::fmt::Display for DbError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "{:?}", self)
+///     }
+/// }
+///
+/// impl std::error::Error for DbError {}
+///
+/// fn find_user(id: u64) -> Result<(), TracebackError> {
+///     Err(DbError::NotFound { id })?
+/// }
+///
+/// let mut error = find_user(7).unwrap_err();
+/// assert_eq!(error.message, "user 7 not found");
+/// assert_eq!(error.level, traceback_error::ErrorLevel::Warn);
+/// assert_eq!(error.extra_data["id"], 7);
+/// assert_eq!(error.diagnostic.as_ref().unwrap().code.as_deref(), Some("DB001"));
+/// assert!(matches!(
+///     error.downcast_ref::<DbError>(),
+///     Some(DbError::NotFound { id: 7 })
+/// ));
+/// error.is_handled = true;
+/// ```
+///
+/// Fields can have any name, including ones used by the generated code:
+///
+/// ```rust
+/// #[derive(Debug, traceback_error::Traceback)]
+/// #[traceback(message = "request failed: {error}")]
+/// struct RequestError {
+///     error: String,
+///     extra_data: u32,
+/// }
+///
+/// impl std::fmt::Display for RequestError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "{}", self.error)
+///     }
+/// }
+///
+/// impl std::error::Error for RequestError {}
+///
+/// let mut error = traceback_error::TracebackError::from(RequestError {
+///     error: "timeout".to_string(),
+///     extra_data: 3,
+/// });
+/// assert_eq!(error.message, "request failed: timeout");
+/// assert_eq!(error.extra_data["error"], "timeout");
+/// assert_eq!(error.extra_data["extra_data"], 3);
+/// error.is_handled = true;
+/// ```
+///
+/// Misspelled level names are rejected when compiling:
+///
+/// ```compile_fail
+/// #[derive(Debug, traceback_error::Traceback)]
+/// #[traceback(level = "warnn")]
+/// struct Timeout;
+///
+/// impl std::fmt::Display for Timeout {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "timed out")
+///     }
+/// }
+///
+/// impl std::error::Error for Timeout {}
+/// ```
+#[cfg(feature = "derive")]
+pub use traceback_error_macros::Traceback;
+
 /// # Traceback Error Callback
 ///
 /// The `TRACEBACK_ERROR_CALLBACK` is a mutable static variable that holds an
@@ -40,10 +243,12 @@ pub use serde_json;
 ///     println!("Custom traceback callback called: {:?}", error);
 /// }
 ///
-/// // Use the set_traceback macro to set the custom traceback callback
-/// traceback_error::set_traceback!(my_traceback_callback);
+/// fn main() {
+///     // Use the set_traceback macro to set the custom traceback callback
+///     traceback_error::set_traceback!(my_traceback_callback);
 ///
-/// // Any TracebackErrors will now be handled by my_traceback_callback when dropped
+///     // Any TracebackErrors will now be handled by my_traceback_callback when dropped
+/// }
 /// ```
 ///
 /// ## Asynchronous Callbacks
@@ -60,22 +265,13 @@ pub use serde_json;
 ///     println!("Async custom traceback callback called: {:?}", error);
 /// }
 ///
-/// // Use the set_traceback macro to set the asynchronous custom traceback callback
-/// traceback_error::set_traceback!(async my_async_traceback_callback);
+/// fn main() {
+///     // Use the set_traceback macro to set the asynchronous custom traceback callback
+///     traceback_error::set_traceback!(async my_async_traceback_callback);
+/// }
 /// ```
 pub static mut TRACEBACK_ERROR_CALLBACK: Option<TracebackCallbackType> = None;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub enum ErrorLevel {
-    None,
-    Unknown,
-    Log,
-    Debug,
-    Warn,
-    Error,
-    Other(String),
-}
-
 /// A custom error struct for handling tracebacks in Rust applications.
 ///
 /// This struct is designed to capture error information such as the error message,
@@ -95,15 +291,44 @@ pub enum ErrorLevel {
 ///
 /// # Fields
 ///
+/// - `id`: A unique `ErrorId` for this node of the chain. `reference()` derives a short
+///   code like `ERR-7F3K9Q` from it that can be shown to end users.
+/// - `parent_id`: The id of the `parent` error, if any.
+/// - `fingerprint`: A stable hash of the chain's locations, message templates and levels,
+///   used to group identical failures. It is filled in when the error is handled, and can
+///   be computed at any time with `fingerprint()`.
 /// - `message`: A string containing the error message.
 /// - `file`: A string containing the filename where the error occurred.
 /// - `line`: An unsigned integer representing the line number where the error occurred.
-/// - `parent`: An optional boxed `TracebackError` representing the parent error, if any.
+/// - `module_path`: The module the error was created in, when created with `traceback!`.
+///   Level filters match on it.
+/// - `parent`: An optional `TracebackError` representing the parent error, if any. It is
+///   kept in an `Arc`, so cloning an error shares its chain instead of copying it.
+/// - `causes`: The errors an aggregate error was made of, see `aggregate`. Each of them
+///   can have parents and causes of its own, and is shared like `parent`.
 /// - `time_created`: A `chrono::DateTime<Utc>` indicating when the error was created.
-/// - `extra_data`: A `serde_json::Value` for storing additional error-related data.
+/// - `extra_data`: A `serde_json::Value` for storing additional error-related data. Any
+///   context values pushed with the `context!` macro are merged into it on creation.
+/// - `metadata`: A `serde_json::Map` filled by the registered enrichers when the error is
+///   handled, kept apart from `extra_data`.
+/// - `breadcrumbs`: The events recorded with the `breadcrumb!` macro before the error was
+///   handled, oldest first.
+/// - `span_trace`: The `tracing` spans active when the error was created, innermost
+///   first. Only captured with the `tracing` feature and a `tracing_error::ErrorLayer`.
+/// - `backtrace`: An optional rendered backtrace, filled in when the error is converted
+///   from an error type that captured one, like `anyhow::Error`.
+/// - `diagnostic`: An optional `Diagnostic` pointing into the source text the error is
+///   about, like a config file, with labels, help and notes.
+/// - `source`: The original error this one was converted from, if any, which can be
+///   recovered with `downcast_ref`. It is not serialized.
 /// - `project`: An optional string representing the project name.
 /// - `computer`: An optional string representing the computer name.
 /// - `user`: An optional string representing the username.
+/// - `env_vars`: The environment variables matching the allowlist set with
+///   `env_capture::set_env_allowlist`, with secret-looking values redacted.
+/// - `process`: An optional `ProcessContext` describing the process and thread the error
+///   was created on. Only captured when enabled with
+///   `process_context::set_capture_process_context`.
 /// - `is_parent`: A boolean indicating if this error is considered a parent error.
 /// - `is_handled`: A boolean indicating if the error has been handled.
 /// - `is_default`: A boolean indicating if this error is the default error.
@@ -113,15 +338,28 @@ pub enum ErrorLevel {
 /// The `Default` trait is implemented for `TracebackError`, creating a default instance
 /// with the following values:
 ///
+/// - `id`: The nil id
+/// - `parent_id`: None
+/// - `fingerprint`: None
 /// - `message`: "Default message"
 /// - `file`: The current file's name (using `file!()`).
 /// - `line`: The current line number (using `line!()`).
+/// - `module_path`: None
 /// - `parent`: None
+/// - `causes`: An empty list
 /// - `time_created`: The Unix epoch time.
 /// - `extra_data`: Value::Null
+/// - `metadata`: An empty map
+/// - `breadcrumbs`: An empty list
+/// - `span_trace`: An empty list
+/// - `backtrace`: None
+/// - `diagnostic`: None
+/// - `source`: None
 /// - `project`: None
 /// - `computer`: None
 /// - `user`: None
+/// - `env_vars`: An empty map
+/// - `process`: None
 /// - `is_parent`: false
 /// - `is_handled`: false
 /// - `is_default`: true
@@ -130,8 +368,8 @@ pub enum ErrorLevel {
 ///
 /// The `PartialEq` trait is implemented for `TracebackError`, allowing you to compare
 /// two `TracebackError` instances for equality based on their message, file, line, and
-/// other relevant fields. The `is_handled` and `is_default` fields are not considered
-/// when comparing for equality.
+/// other relevant fields. The `id`, `parent_id`, `fingerprint`, `is_handled` and
+/// `is_default` fields are not considered when comparing for equality.
 ///
 /// # Dropping Errors
 ///
@@ -141,6 +379,10 @@ pub enum ErrorLevel {
 /// write it to a JSON file, but the default function can be changed with the
 /// `set_callback!` macro.
 ///
+/// Errors can also be reported explicitly with `report`, or marked as swallowed with
+/// `handled`. Reporting on drop can be turned off with `lifecycle::set_implicit_reporting`.
+/// In tests, `leak::track` collects errors dropped without either, instead of reporting them.
+///
 /// # Callback Types
 ///
 /// The callback function can be either synchronous or asynchronous, depending on the
@@ -165,7 +407,8 @@ pub enum ErrorLevel {
 /// The `with_env_vars` method populates the `project`, `computer`, and `user` fields with
 /// information obtained from environment variables (`CARGO_PKG_NAME`, `COMPUTERNAME`, and
 /// `USERNAME`, respectively) or assigns default values if the environment variables are
-/// not present.
+/// not present. It also captures the variables matching the allowlist configured in the
+/// `env_capture` module into the `env_vars` field.
 ///
 /// # Tracing
 ///
@@ -176,15 +419,41 @@ pub enum ErrorLevel {
 /// Should a function return a TracebackError, it can then be re-captured to trace it even further.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TracebackError {
+    #[serde(default)]
+    pub id: ErrorId,
+    #[serde(default)]
+    pub parent_id: Option<ErrorId>,
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>,
     pub message: String,
     pub file: String,
     pub line: u32,
-    pub parent: Option<Box<TracebackError>>,
+    #[serde(default)]
+    pub module_path: Option<String>,
+    pub parent: Option<Arc<TracebackError>>,
+    #[serde(default)]
+    pub causes: Vec<Arc<TracebackError>>,
     pub time_created: DateTime<Utc>,
     pub extra_data: serde_json::Map<String, Value>,
+    #[serde(default)]
+    pub metadata: serde_json::Map<String, Value>,
+    #[serde(default)]
+    pub breadcrumbs: Vec<Breadcrumb>,
+    #[serde(default)]
+    pub span_trace: Vec<SpanInfo>,
+    #[serde(default)]
+    pub backtrace: Option<String>,
+    #[serde(default)]
+    pub diagnostic: Option<Diagnostic>,
+    #[serde(skip)]
+    pub source: Option<Arc<dyn Error + Send + Sync>>,
     pub project: Option<String>,
     pub computer: Option<String>,
     pub user: Option<String>,
+    #[serde(default)]
+    pub env_vars: BTreeMap<String, String>,
+    #[serde(default)]
+    pub process: Option<ProcessContext>,
     pub is_parent: bool,
     pub is_handled: bool,
     pub level: ErrorLevel,
@@ -194,18 +463,28 @@ pub struct TracebackError {
 impl Default for TracebackError {
     fn default() -> Self {
         Self {
+            id: ErrorId::nil(),
+            parent_id: None,
+            fingerprint: None,
             message: "Default message".to_string(),
             file: file!().to_string(),
             line: line!(),
+            module_path: None,
             parent: None,
-            time_created: DateTime::from_utc(
-                chrono::NaiveDateTime::from_timestamp_opt(0, 0).unwrap(),
-                Utc,
-            ),
+            causes: Vec::new(),
+            time_created: Utc.timestamp_opt(0, 0).unwrap(),
             extra_data: Map::new(),
+            metadata: Map::new(),
+            breadcrumbs: Vec::new(),
+            span_trace: Vec::new(),
+            backtrace: None,
+            diagnostic: None,
+            source: None,
             project: None,
             computer: None,
             user: None,
+            env_vars: BTreeMap::new(),
+            process: None,
             is_parent: false,
             is_handled: false,
             is_default: true,
@@ -222,6 +501,7 @@ impl PartialEq for TracebackError {
             && this.file == other.file
             && this.line == other.line
             && this.parent == other.parent
+            && this.causes == other.causes
             && this.extra_data == other.extra_data
             && this.project == other.project
             && this.computer == other.computer
@@ -235,38 +515,41 @@ impl Drop for TracebackError {
         if self.is_parent || self.is_handled || self.is_default {
             return;
         }
-        let mut this = std::mem::take(self);
-        this = this.with_env_vars();
-        this.is_handled = true;
-        unsafe {
-            let callback: Option<&mut TracebackCallbackType> = TRACEBACK_ERROR_CALLBACK.as_mut();
-            match callback {
-                Some(TracebackCallbackType::Async(ref mut f)) => {
-                    block_on::block_on(f.call(this)); // bad practice, fix later
-                }
-                Some(TracebackCallbackType::Sync(ref mut f)) => {
-                    f.call(this);
-                }
-                None => {
-                    default_callback(this);
-                }
-            }
+        if leak::record(self) {
+            return;
         }
+        if !lifecycle::implicit_reporting() {
+            return;
+        }
+        dispatch::dispatch(std::mem::take(self));
     }
 }
 
 impl TracebackError {
     pub fn new(message: String, file: String, line: u32, level: ErrorLevel) -> Self {
         Self {
+            id: ErrorId::new(),
+            parent_id: None,
+            fingerprint: None,
             message,
             file,
             line,
+            module_path: None,
             parent: None,
+            causes: Vec::new(),
             time_created: Utc::now(),
-            extra_data: Map::new(),
+            extra_data: context::current(),
+            metadata: Map::new(),
+            breadcrumbs: Vec::new(),
+            span_trace: span_trace::capture(),
+            backtrace: None,
+            diagnostic: None,
+            source: None,
             project: None,
             computer: None,
             user: None,
+            env_vars: BTreeMap::new(),
+            process: ProcessContext::capture_if_enabled(),
             is_parent: false,
             is_handled: false,
             is_default: false,
@@ -281,7 +564,9 @@ impl TracebackError {
     /// - `extra_data`: A `serde_json::Value` containing the extra data you want to associate with the error.
     ///
     /// ## Return Value:
-    /// - Returns a modified `TracebackError` instance with the provided `extra_data`.
+    /// - Returns a modified `TracebackError` instance with the provided `extra_data`, merged
+    ///   into the existing data with the default `MergePolicy`. A value that is not an
+    ///   object is stored under the key `extra_data`.
     ///
     /// ## Example Usage:
     /// ```rs
@@ -302,43 +587,122 @@ impl TracebackError {
     /// This method is useful when you want to enrich error objects with additional information
     /// relevant to the context in which the error occurred. It ensures that relevant data is
     /// available for analysis when handling errors in your Rust application.
-    pub fn with_extra_data(mut self, extra_data: Value) -> Self {
+    pub fn with_extra_data(self, extra_data: Value) -> Self {
+        self.with_extra_data_using(merge::default_merge_policy(), extra_data)
+    }
+    /// Like `with_extra_data`, but merges with the given policy instead of the default
+    /// one set with `merge::set_default_merge_policy`.
+    ///
+    /// Under `MergePolicy::ErrorOnConflict`, conflicting keys keep their old values and
+    /// their paths are added to `metadata["extra_data_conflicts"]`.
+    pub fn with_extra_data_using(mut self, policy: MergePolicy, extra_data: Value) -> Self {
         self.is_default = false;
-
-        let old_obj = &mut self.extra_data;
-
-        match extra_data {
-            serde_json::Value::Object(new_obj) => {
-                for (key, new_value) in new_obj.iter() {
-                    old_obj
-                        .entry(key.clone())
-                        .and_modify(|old_value| {
-                            if let serde_json::Value::Object(old_obj) = old_value {
-                                if let serde_json::Value::Object(new_obj) = new_value {
-                                    // Recursively merge if both values are objects
-                                    *old_value = serde_json::Value::Object(merge_json_objects(
-                                        old_obj.clone(),
-                                        new_obj.clone(),
-                                    ));
-                                }
-                            } else {
-                                // Replace the old non-object value
-                                *old_value = new_value.clone();
-                            }
-                        })
-                        .or_insert(new_value.clone());
-                }
-            }
-            serde_json::Value::Bool(_)
-            | serde_json::Value::String(_)
-            | serde_json::Value::Number(_)
-            | serde_json::Value::Null
-            | serde_json::Value::Array(_) => {
-                old_obj.insert("extra_data".to_string(), extra_data);
+        let conflicts = merge::merge(&mut self.extra_data, into_object(extra_data), policy);
+        if !conflicts.is_empty() {
+            let recorded = self
+                .metadata
+                .entry("extra_data_conflicts")
+                .or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(recorded) = recorded {
+                recorded.extend(conflicts.into_iter().map(Value::String));
             }
         }
         self
     }
+    /// Merges data into `extra_data` with the given policy, leaving it untouched if any
+    /// key conflicts under `MergePolicy::ErrorOnConflict`.
+    ///
+    /// ```rust
+    /// use traceback_error::{merge::MergePolicy, serde_json::json, traceback};
+    ///
+    /// let mut error = traceback!("Payment failed").with_extra_data(json!({ "amount": 10 }));
+    /// let result = error.try_merge_extra_data(MergePolicy::ErrorOnConflict, json!({ "amount": 12 }));
+    /// assert_eq!(result, Err("conflicting values for amount".to_string()));
+    /// assert_eq!(error.extra_data["amount"], json!(10));
+    /// error.is_handled = true;
+    /// ```
+    pub fn try_merge_extra_data(
+        &mut self,
+        policy: MergePolicy,
+        extra_data: Value,
+    ) -> Result<(), String> {
+        let mut merged = self.extra_data.clone();
+        let conflicts = merge::merge(&mut merged, into_object(extra_data), policy);
+        if !conflicts.is_empty() {
+            return Err(format!("conflicting values for {}", conflicts.join(", ")));
+        }
+        self.is_default = false;
+        self.extra_data = merged;
+        Ok(())
+    }
+    /// Stores any serializable value in `extra_data` under `key`, replacing what was
+    /// there.
+    ///
+    /// If the value fails to serialize, the serialization error is stored as a string
+    /// instead, so the failure shows up in the report.
+    ///
+    /// ```rust
+    /// use traceback_error::traceback;
+    ///
+    /// #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    /// struct Request {
+    ///     method: String,
+    ///     status: u16,
+    /// }
+    ///
+    /// let request = Request { method: "GET".to_string(), status: 503 };
+    /// let mut error = traceback!("Upstream failed")
+    ///     .with("http", &request)
+    ///     .with("attempt", &3);
+    ///
+    /// assert_eq!(error.get::<u32>("attempt"), Some(3));
+    /// assert_eq!(error.get::<Request>("http"), Some(request));
+    /// assert_eq!(error.get_path::<u16>("http.status"), Some(503));
+    /// assert!(error.contains("attempt"));
+    /// assert!(error.remove("attempt").is_some());
+    /// assert!(!error.contains("attempt"));
+    /// error.is_handled = true;
+    /// ```
+    pub fn with<T: Serialize + ?Sized>(mut self, key: &str, value: &T) -> Self {
+        self.is_default = false;
+        let value = serde_json::to_value(value)
+            .unwrap_or_else(|e| Value::String(format!("failed to serialize: {}", e)));
+        self.extra_data.insert(key.to_string(), value);
+        self
+    }
+    /// Reads the value stored in `extra_data` under `key` as a `T`.
+    ///
+    /// Returns `None` if there is no such key, or if its value does not deserialize
+    /// into a `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        T::deserialize(self.extra_data.get(key)?).ok()
+    }
+    /// Reads a nested value in `extra_data` as a `T`, following a path of keys
+    /// separated by dots, like `http.status`. Segments that are numbers index into
+    /// arrays.
+    ///
+    /// Returns `None` if the path does not exist, or if its value does not deserialize
+    /// into a `T`.
+    pub fn get_path<T: DeserializeOwned>(&self, path: &str) -> Option<T> {
+        let mut segments = path.split('.');
+        let mut value = self.extra_data.get(segments.next()?)?;
+        for segment in segments {
+            value = match value {
+                Value::Object(map) => map.get(segment)?,
+                Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        T::deserialize(value).ok()
+    }
+    /// Removes the value stored in `extra_data` under `key`, returning it.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.extra_data.remove(key)
+    }
+    /// Returns whether `extra_data` has a value under `key`.
+    pub fn contains(&self, key: &str) -> bool {
+        self.extra_data.contains_key(key)
+    }
     /// Adds environment variables to the TracebackError.
     ///
     /// This method populates the `project`, `computer`, and `user` fields of the `TracebackError`
@@ -352,8 +716,13 @@ impl TracebackError {
     /// use traceback_error::TracebackError;
     ///
     /// // Create a new TracebackError and populate environment variables
-    /// let error = TracebackError::new("An error occurred".to_string(), file!().to_string(), line!())
-    ///     .with_env_vars();
+    /// let error = TracebackError::new(
+    ///     "An error occurred".to_string(),
+    ///     file!().to_string(),
+    ///     line!(),
+    ///     traceback_error::ErrorLevel::Error,
+    /// )
+    /// .with_env_vars();
     ///
     /// // The error now contains information about the project, computer, and user from
     /// // environment variables, or default values if the environment variables are missing.
@@ -365,9 +734,12 @@ impl TracebackError {
     /// - `COMPUTERNAME`: Used to set the `computer` field.
     /// - `USERNAME`: Used to set the `user` field.
     ///
+    /// Any other variables matching the allowlist set with `env_capture::set_env_allowlist`
+    /// are captured into the `env_vars` field.
+    ///
     /// # Returns:
     ///
-    /// A modified `TracebackError` with updated `project`, `computer`, and `user` fields.
+    /// A modified `TracebackError` with updated `project`, `computer`, `user` and `env_vars` fields.
     pub fn with_env_vars(mut self) -> Self {
         // get project name using the CARGO_PKG_NAME env variable
         let project_name = match std::env::var("CARGO_PKG_NAME") {
@@ -388,6 +760,7 @@ impl TracebackError {
         self.project = Some(project_name);
         self.computer = Some(computer_name);
         self.user = Some(username);
+        self.env_vars = env_capture::capture_env_vars();
         self
     }
     /// The `with_parent` method allows you to associate a parent error with the current `TracebackError` instance.
@@ -419,9 +792,166 @@ impl TracebackError {
     /// making it easier to understand error hierarchies and diagnose issues.
     pub fn with_parent(mut self, parent: TracebackError) -> Self {
         self.is_default = false;
-        self.parent = Some(Box::new(parent.with_is_parent(true)));
+        self.parent_id = Some(parent.id);
+        self.parent = Some(Arc::new(parent.with_is_parent(true)));
         self
     }
+    /// Creates an error made of several others, such as the failures of tasks that ran in
+    /// parallel. The errors become its `causes`, and it gets the level of the most severe
+    /// one and the location of the caller.
+    ///
+    /// ```rust
+    /// use traceback_error::{traceback, TracebackError};
+    ///
+    /// let results: Vec<Result<u32, TracebackError>> = vec![
+    ///     Ok(1),
+    ///     Err(traceback!("shard 2 timed out")),
+    ///     Ok(3),
+    ///     Err(traceback!("shard 4 refused the connection")),
+    /// ];
+    /// let mut error = TracebackError::collect("Fan-out failed", results).unwrap_err();
+    ///
+    /// assert_eq!(error.causes.len(), 2);
+    /// assert_eq!(error.causes[1].message, "shard 4 refused the connection");
+    /// assert!(error.to_string().contains("\tsrc/lib.rs"));
+    /// error.is_handled = true;
+    /// ```
+    #[track_caller]
+    pub fn aggregate<I>(message: &str, errors: I) -> Self
+    where
+        I: IntoIterator<Item = TracebackError>,
+    {
+        let location = std::panic::Location::caller();
+        let causes: Vec<Arc<TracebackError>> = errors
+            .into_iter()
+            .map(|error| Arc::new(error.with_is_parent(true)))
+            .collect();
+        let level = causes
+            .iter()
+            .map(|cause| cause.level.clone())
+            .max()
+            .unwrap_or(ErrorLevel::Error);
+        let mut error = TracebackError::new(
+            message.to_string(),
+            location.file().to_string(),
+            location.line(),
+            level,
+        );
+        error.causes = causes;
+        error
+    }
+    /// Collects the values of an iterator of results, or returns an aggregate error made
+    /// of every error in it.
+    // Returning `TracebackError` is the point of this crate, however large it is
+    #[allow(clippy::result_large_err)]
+    #[track_caller]
+    pub fn collect<T, I>(message: &str, results: I) -> Result<Vec<T>, TracebackError>
+    where
+        I: IntoIterator<Item = Result<T, TracebackError>>,
+    {
+        let mut values = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(error) => errors.push(error),
+            }
+        }
+        if errors.is_empty() {
+            Ok(values)
+        } else {
+            Err(Self::aggregate(message, errors))
+        }
+    }
+    /// Reports the error now, instead of when it is dropped.
+    ///
+    /// The error goes through the same filters, enrichers and callback as a dropped one,
+    /// whether or not it was marked as handled, and even when implicit reporting is
+    /// disabled with `lifecycle::set_implicit_reporting`.
+    pub fn report(mut self) {
+        self.is_default = false;
+        dispatch::dispatch(std::mem::take(&mut self));
+    }
+    /// Marks the error as intentionally swallowed, so it is not reported when dropped.
+    ///
+    /// A reason, if given, is recorded in `metadata["handled_reason"]`, which helps when
+    /// the error is inspected or reported later anyway.
+    ///
+    /// ```rust
+    /// let mut error = traceback_error::traceback!("Cache miss");
+    /// error.handled(Some("falling back to the database"));
+    /// assert!(error.is_handled);
+    ///
+    /// // Changed our mind: report it when it is dropped after all
+    /// error.defer();
+    /// assert!(!error.is_handled);
+    /// # error.handled(None);
+    /// ```
+    pub fn handled(&mut self, reason: Option<&str>) {
+        self.is_handled = true;
+        match reason {
+            Some(reason) => {
+                self.metadata
+                    .insert("handled_reason".to_string(), json!(reason));
+            }
+            None => {
+                self.metadata.remove("handled_reason");
+            }
+        }
+    }
+    /// Undoes `handled`, leaving it to the drop to report the error, if implicit reporting
+    /// is enabled.
+    pub fn defer(&mut self) {
+        self.is_handled = false;
+        self.metadata.remove("handled_reason");
+    }
+    /// Returns the fingerprint identifying this kind of failure.
+    ///
+    /// If the `fingerprint` field is already set it is returned as is, otherwise it is
+    /// computed with the key function configured in the `fingerprint` module.
+    pub fn fingerprint(&self) -> Fingerprint {
+        match self.fingerprint {
+            Some(fingerprint) => fingerprint,
+            None => fingerprint::compute(self),
+        }
+    }
+    /// Returns the short, user-facing reference for this error, like `ERR-7F3K9Q`.
+    ///
+    /// The default callback names report files after the error id, so a report can be
+    /// found again from its reference with `find_report`.
+    pub fn reference(&self) -> String {
+        self.id.reference()
+    }
+    /// Attaches a diagnostic describing where in some source text the error is, which
+    /// `diagnostic::render` draws.
+    pub fn with_diagnostic(mut self, diagnostic: Diagnostic) -> Self {
+        self.diagnostic = Some(diagnostic);
+        self
+    }
+    /// Sets the module the error was created in, which level filters match on.
+    /// The `traceback!` macro sets it to `module_path!()`.
+    pub fn with_module_path(mut self, module_path: &str) -> Self {
+        self.module_path = Some(module_path.to_string());
+        self
+    }
+    /// Keeps the error this one was converted from, so it can be recovered with
+    /// `downcast_ref` and is returned by `Error::source`.
+    pub fn with_source<E: Error + Send + Sync + 'static>(mut self, source: E) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+    /// Returns the original error this error was converted from, if it has type `E`.
+    /// If it does not, the parents are searched, closest first.
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        let mut node = Some(self);
+        while let Some(current) = node {
+            if let Some(source) = current.source.as_deref().and_then(|s| s.downcast_ref()) {
+                return Some(source);
+            }
+            node = current.parent.as_deref();
+        }
+        None
+    }
     fn with_is_parent(mut self, is_parent: bool) -> Self {
         self.is_default = false;
         self.is_parent = is_parent;
@@ -430,7 +960,8 @@ impl TracebackError {
 }
 
 /// This display implementation is recursive, and will print the error and all its parents
-/// with a tab in front of each parent.
+/// with a tab in front of each parent. The causes of an aggregate error are printed
+/// below it, indented one tab further.
 impl Display for TracebackError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut parent = self.parent.as_ref();
@@ -440,7 +971,7 @@ impl Display for TracebackError {
             if first {
                 first = false;
             } else {
-                write!(f, "\n")?;
+                writeln!(f)?;
             }
             for _ in 0..amount_tabs {
                 write!(f, "\t")?;
@@ -449,35 +980,69 @@ impl Display for TracebackError {
             amount_tabs += 1;
             parent = p.parent.as_ref();
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
         for _ in 0..amount_tabs {
             write!(f, "\t")?;
         }
-        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)?;
+        for cause in &self.causes {
+            let cause = cause.to_string();
+            for line in cause.lines().filter(|line| !line.is_empty()) {
+                writeln!(f)?;
+                for _ in 0..=amount_tabs {
+                    write!(f, "\t")?;
+                }
+                write!(f, "{}", line)?;
+            }
+        }
+        Ok(())
     }
 }
 
-impl Error for TracebackError {}
+impl Error for TracebackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.source {
+            Some(source) => Some(source.as_ref()),
+            None => None,
+        }
+    }
+}
 
 impl serde::de::Error for TracebackError {
     fn custom<T: std::fmt::Display>(msg: T) -> Self {
         // Create a new TracebackError with the provided message
         TracebackError {
+            id: ErrorId::new(),
+            parent_id: None,
+            fingerprint: None,
             message: msg.to_string(),
             file: String::new(),
             line: 0,
+            module_path: None,
             parent: None,
+            causes: Vec::new(),
             time_created: Utc::now(),
-            extra_data: json!({
-                "error_type": "serde::de::Error",
-                "error_message": msg.to_string()
-            })
-            .as_object()
-            .unwrap()
-            .clone(),
+            extra_data: merge_json_objects(
+                context::current(),
+                json!({
+                    "error_type": "serde::de::Error",
+                    "error_message": msg.to_string()
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            ),
+            metadata: Map::new(),
+            breadcrumbs: Vec::new(),
+            span_trace: span_trace::capture(),
+            backtrace: None,
+            diagnostic: None,
+            source: None,
             project: None,
             computer: None,
             user: None,
+            env_vars: BTreeMap::new(),
+            process: ProcessContext::capture_if_enabled(),
             is_parent: false,
             is_handled: false,
             is_default: false,
@@ -498,15 +1063,14 @@ impl serde::de::Error for TracebackError {
 /// set to a custom callback, the `default_callback` function is used. This function
 /// performs the following actions:
 ///
-/// 1. Retrieves the current time in UTC and creates a timestamp string.
-/// 2. Checks if the "errors" folder exists and creates it if it doesn't.
-/// 3. Generates a unique filename based on the current timestamp.
-/// 4. Writes the error information in JSON format to a file with the generated filename
-///    in the "errors" folder.
-/// 5. Logs any encountered errors during the above steps.
+/// 1. Checks if the "errors" folder exists and creates it if it doesn't.
+/// 2. Writes the error information in JSON format to a file named after the error's
+///    `id` in the "errors" folder.
+/// 3. Logs any encountered errors during the above steps.
 ///
-/// This default behavior ensures that unhandled errors are captured, timestamped,
-/// and saved as JSON files for later analysis.
+/// This default behavior ensures that unhandled errors are captured and saved as JSON
+/// files for later analysis. Since ids are unique, and their last characters make up
+/// the error's reference, a report can be looked up from a reference with `find_report`.
 ///
 /// ## Usage
 ///
@@ -518,7 +1082,7 @@ impl serde::de::Error for TracebackError {
 ///
 /// ```rust
 /// // No custom callback set, so the default_callback will be used
-/// traceback_error::set_traceback!(None);
+/// traceback_error::set_callback::reset_traceback_callback();
 ///
 /// // Any TracebackErrors will now be handled by the default_callback when dropped
 /// ```
@@ -526,11 +1090,6 @@ impl serde::de::Error for TracebackError {
 /// To customize error handling, you can set a custom callback using the `set_traceback!`
 /// macro as shown in the documentation for `TRACEBACK_ERROR_CALLBACK`.
 pub fn default_callback(err: TracebackError) {
-    // get current time
-    let current_time = chrono::Utc::now();
-    let current_time_string = current_time.format("%Y-%m-%d.%H-%M-%S").to_string();
-    let nanosecs = current_time.timestamp_nanos();
-    let current_time_string = format!("{}.{}", current_time_string, nanosecs);
     // check if errors folder exists
     match std::fs::read_dir("errors") {
         Ok(_) => {}
@@ -545,8 +1104,8 @@ pub fn default_callback(err: TracebackError) {
             };
         }
     };
-    // create {current_time_string}.json
-    let filename = format!("./errors/{current_time_string}.json");
+    // create {id}.json
+    let filename = format!("./errors/{}.json", err.id);
     println!("Writing error to file: {}", filename);
     let mut file = match File::create(filename) {
         Ok(f) => f,
@@ -568,10 +1127,50 @@ pub fn default_callback(err: TracebackError) {
         Ok(_) => {}
         Err(e) => {
             println!("Error when writing to file: {}", e);
-            return;
         }
     };
 }
+
+/// Finds the report file `default_callback` wrote for the error with the given
+/// reference, like `ERR-7F3K9Q`.
+///
+/// The reference may be given with or without its `ERR-` prefix. Returns `None` if
+/// the "errors" folder does not contain a matching report.
+///
+/// ```rust
+/// use traceback_error::{find_report, traceback, TracebackError};
+///
+/// traceback_error::set_callback::reset_traceback_callback();
+/// let parent = traceback!("Disk full");
+/// let parent_id = parent.id;
+/// let error = traceback!(err parent);
+/// assert_eq!(error.parent_id, Some(parent_id));
+/// let (id, reference) = (error.id, error.reference());
+/// error.report();
+///
+/// // The report is named after the id, and found again from the reference
+/// let path = find_report(&reference).unwrap();
+/// assert_eq!(path.file_stem().unwrap().to_str(), Some(id.to_string().as_str()));
+/// assert_eq!(find_report(reference.trim_start_matches("ERR-")), Some(path.clone()));
+///
+/// let mut report: TracebackError =
+///     traceback_error::serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+/// assert_eq!(report.parent_id, Some(parent_id));
+/// report.is_handled = true;
+/// std::fs::remove_file(path).unwrap();
+/// ```
+pub fn find_report(reference: &str) -> Option<PathBuf> {
+    let suffix = format!("{}.json", ErrorId::reference_suffix(reference));
+    std::fs::read_dir("errors")
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(&suffix))
+        })
+}
 /// A macro for creating instances of the `TracebackError` struct with various options.
 ///
 /// The `traceback!` macro simplifies the creation of `TracebackError` instances by providing
@@ -699,8 +1298,8 @@ pub fn default_callback(err: TracebackError) {
 ///   using the current file and line number.
 ///
 /// - `traceback!(err $e:expr)`: Attempts to downcast the provided error (`$e`) to a
-///   `TracebackError`. If successful, it marks the error as handled and creates a new
-///   `TracebackError` instance based on the downcasted error. If the downcast fails, it
+///   `TracebackError`. If successful, it creates a new `TracebackError` instance with the
+///   downcasted error moved in as its parent. If the downcast fails, it
 ///   creates a `TracebackError` with an empty message and includes the original error's
 ///   description in the extra data field.
 ///
@@ -709,9 +1308,9 @@ pub fn default_callback(err: TracebackError) {
 ///
 /// # Error Handling
 ///
-/// When using the `traceback!` macro to create `TracebackError` instances from other error types,
-/// it automatically sets the `is_handled` flag to `true` for the original error to indicate that
-/// it has been handled. This prevents the `TRACEBACK_ERROR_CALLBACK` function to be called on it.
+/// When using the `traceback!` macro to wrap a `TracebackError`, the original error is moved
+/// into the new error's chain as its parent, without copying it. Parents are never handled on
+/// their own, which prevents the `TRACEBACK_ERROR_CALLBACK` function from being called on it.
 ///
 /// # Environment Variables
 ///
@@ -723,73 +1322,76 @@ pub fn default_callback(err: TracebackError) {
 macro_rules! traceback {
     () => {
         $crate::TracebackError::new("".to_string(), file!().to_string(), line!(), $crate::ErrorLevel::Unknown)
+            .with_module_path(module_path!())
     };
     ($msg:expr) => {
         $crate::TracebackError::new($msg.to_string(), file!().to_string(), line!(), $crate::ErrorLevel::Unknown)
+            .with_module_path(module_path!())
     };
     (err $e:expr) => {{
         use $crate::serde_json::json;
-        let err_string = $e.to_string();
-        let mut boxed: Box<dyn std::any::Any> = Box::new($e);
-        if let Some(traceback_err) = boxed.downcast_mut::<$crate::TracebackError>() {
-            traceback_err.is_handled = true;
+        let error = $e;
+        if (&error as &dyn std::any::Any).is::<$crate::TracebackError>() {
+            let boxed: Box<dyn std::any::Any> = Box::new(error);
+            // Moved into the chain, which keeps it from being reported on its own
+            let parent = *boxed.downcast::<$crate::TracebackError>().unwrap();
             $crate::TracebackError::new(
-                traceback_err.message.to_string(),
+                parent.message.to_string(),
                 file!().to_string(),
                 line!(),
                 $crate::ErrorLevel::Unknown,
             )
-            .with_parent(traceback_err.clone())
+            .with_module_path(module_path!())
+            .with_parent(parent)
         } else {
             $crate::TracebackError::new(String::from(""), file!().to_string(), line!(), $crate::ErrorLevel::Unknown)
+                .with_module_path(module_path!())
                 .with_extra_data(json!({
-                    "parent_error": err_string
+                    "parent_error": error.to_string()
                 }))
         }
     }};
     (err $e:expr, $msg:expr) => {{
         use $crate::serde_json::json;
-        let err_string = $e.to_string();
-        let mut boxed: Box<dyn std::any::Any> = Box::new($e);
-        if let Some(traceback_err) = boxed.downcast_mut::<$crate::TracebackError>() {
-            traceback_err.is_handled = true;
+        let error = $e;
+        if (&error as &dyn std::any::Any).is::<$crate::TracebackError>() {
+            let boxed: Box<dyn std::any::Any> = Box::new(error);
+            // Moved into the chain, which keeps it from being reported on its own
+            let parent = *boxed.downcast::<$crate::TracebackError>().unwrap();
             $crate::TracebackError::new(
                 $msg.to_string(),
                 file!().to_string(),
                 line!(),
                 $crate::ErrorLevel::Unknown,
             )
-            .with_parent(traceback_err.clone())
+            .with_module_path(module_path!())
+            .with_parent(parent)
         } else {
             $crate::TracebackError::new($msg.to_string(), file!().to_string(), line!(), $crate::ErrorLevel::Unknown)
+                .with_module_path(module_path!())
                 .with_extra_data(json!({
-                    "parent_error": err_string
+                    "parent_error": error.to_string()
                 }))
         }
     }};
 }
 
-fn merge_json_objects(
+pub(crate) fn merge_json_objects(
     mut obj1: serde_json::Map<String, serde_json::Value>,
     obj2: serde_json::Map<String, serde_json::Value>,
 ) -> serde_json::Map<String, serde_json::Value> {
-    for (key, value) in obj2 {
-        obj1.entry(key)
-            .and_modify(|old_value| {
-                if let serde_json::Value::Object(old_obj) = old_value {
-                    if let serde_json::Value::Object(new_obj) = value.clone() {
-                        // Recursively merge if both values are objects
-                        *old_value = serde_json::Value::Object(merge_json_objects(
-                            old_obj.clone(),
-                            new_obj.clone(),
-                        ));
-                    }
-                } else {
-                    // Replace the old non-object value
-                    *old_value = value.clone();
-                }
-            })
-            .or_insert(value);
-    }
+    merge::merge(&mut obj1, obj2, merge::default_merge_policy());
     obj1
 }
+
+// Extra data that is not an object is stored under the key `extra_data`
+fn into_object(extra_data: Value) -> serde_json::Map<String, Value> {
+    match extra_data {
+        Value::Object(obj) => obj,
+        other => {
+            let mut obj = serde_json::Map::new();
+            obj.insert("extra_data".to_string(), other);
+            obj
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static IMPLICIT_REPORTING: AtomicBool = AtomicBool::new(true);
+
+/// Chooses whether errors that are dropped without being reported or handled are
+/// reported anyway, which is the default.
+///
+/// With implicit reporting disabled, errors only reach the callback through
+/// `TracebackError::report`, and dropping an error does nothing.
+///
+/// ```rust
+/// use traceback_error::{lifecycle::set_implicit_reporting, testing, traceback};
+///
+/// set_implicit_reporting(false);
+/// let capture = testing::capture();
+///
+/// drop(traceback!("Cache miss")); // not reported
+/// traceback!("Database unreachable").report(); // reported
+///
+/// let errors = capture.take();
+/// assert_eq!(errors.len(), 1);
+/// assert_eq!(errors[0].message, "Database unreachable");
+/// ```
+pub fn set_implicit_reporting(enabled: bool) {
+    IMPLICIT_REPORTING.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns whether dropped errors are reported, see `set_implicit_reporting`.
+pub fn implicit_reporting() -> bool {
+    IMPLICIT_REPORTING.load(Ordering::Relaxed)
+}
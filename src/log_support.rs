@@ -0,0 +1,247 @@
+use log::{
+    kv::{self, Key, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::{set_callback::TracebackCallback, TracebackError};
+
+/// The target of the records `LogSink` logs. `TracebackLogger` does not report records
+/// with this target, so the two can be used together.
+pub const TARGET: &str = "traceback_error";
+
+/// A callback that logs handled errors through the `log` facade, at the `log` level
+/// their `ErrorLevel` maps to.
+///
+/// The record's message is the error message. The id, reference and fingerprint, and
+/// every top-level key of `extra_data`, are attached as structured key-values.
+///
+/// ```rust
+/// use log::kv::{self, Key, VisitSource};
+/// use std::{collections::BTreeMap, sync::Mutex};
+/// use traceback_error::{
+///     log_support::LogSink,
+///     serde_json::json,
+///     set_callback::{set_traceback_callback, TracebackCallbackType},
+///     ErrorLevel,
+/// };
+///
+/// #[derive(Default)]
+/// struct KeyValues(BTreeMap<String, String>);
+///
+/// impl<'kvs> VisitSource<'kvs> for KeyValues {
+///     fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+///         self.0.insert(key.to_string(), value.to_string());
+///         Ok(())
+///     }
+/// }
+///
+/// // Records the level, target and key-values of every record
+/// static RECORDS: Mutex<Vec<(log::Level, String, BTreeMap<String, String>)>> =
+///     Mutex::new(Vec::new());
+///
+/// struct Recorder;
+///
+/// impl log::Log for Recorder {
+///     fn enabled(&self, _metadata: &log::Metadata) -> bool {
+///         true
+///     }
+///
+///     fn log(&self, record: &log::Record) {
+///         let mut key_values = KeyValues::default();
+///         record.key_values().visit(&mut key_values).unwrap();
+///         let record = (record.level(), record.target().to_string(), key_values.0);
+///         RECORDS.lock().unwrap().push(record);
+///     }
+///
+///     fn flush(&self) {}
+/// }
+///
+/// log::set_logger(&Recorder).unwrap();
+/// log::set_max_level(log::LevelFilter::Trace);
+/// set_traceback_callback(TracebackCallbackType::Sync(Box::new(LogSink)));
+///
+/// let mut error = traceback_error::traceback!("Query failed")
+///     .with_extra_data(json!({ "table": "users", "attempt": 3 }));
+/// error.level = ErrorLevel::Warn;
+/// let id = error.id.to_string();
+/// drop(error);
+///
+/// let records = RECORDS.lock().unwrap();
+/// let (level, target, key_values) = &records[0];
+/// assert_eq!(*level, log::Level::Warn);
+/// assert_eq!(target, "traceback_error");
+/// assert_eq!(key_values["table"], "users");
+/// assert_eq!(key_values["attempt"], "3");
+/// assert_eq!(key_values["id"], id);
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogSink;
+
+impl TracebackCallback for LogSink {
+    fn call(&self, error: TracebackError) {
+        let mut key_values = BTreeMap::new();
+        for (key, value) in &error.extra_data {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            key_values.insert(key.clone(), value);
+        }
+        // Set last, so extra data cannot pass itself off as the error's identity
+        key_values.insert("id".to_string(), error.id.to_string());
+        key_values.insert("reference".to_string(), error.reference());
+        key_values.insert("fingerprint".to_string(), error.fingerprint().to_string());
+
+        log::logger().log(
+            &Record::builder()
+                .level(Level::from(&error.level))
+                .target(TARGET)
+                .file(Some(&error.file))
+                .line(Some(error.line))
+                .module_path(error.module_path.as_deref())
+                .args(format_args!("{}", error.message))
+                .key_values(&key_values)
+                .build(),
+        );
+    }
+}
+
+/// A `log::Log` implementation that reports records as `TracebackError`s, and
+/// optionally passes every record on to another logger.
+///
+/// By default, only `error!` records are reported. The record's message becomes the
+/// error message, its location the error's file, line and module, and its key-values
+/// the error's extra data. The reports go to the configured callback like any other
+/// error.
+///
+/// ```rust
+/// use std::sync::Mutex;
+/// use traceback_error::TracebackError;
+///
+/// static REPORTED: Mutex<Vec<TracebackError>> = Mutex::new(Vec::new());
+///
+/// fn record(error: TracebackError) {
+///     REPORTED.lock().unwrap().push(error);
+/// }
+///
+/// fn main() {
+///     traceback_error::set_traceback!(record);
+///
+///     let logger = traceback_error::log_support::TracebackLogger::new(log::LevelFilter::Error);
+///     log::set_boxed_logger(Box::new(logger)).unwrap();
+///     log::set_max_level(log::LevelFilter::Info);
+///
+///     log::info!("not reported");
+///     log::error!(attempt = 3; "Connection refused");
+///
+///     let reported = REPORTED.lock().unwrap();
+///     assert_eq!(reported.len(), 1);
+///     assert_eq!(reported[0].message, "Connection refused");
+///     assert_eq!(reported[0].extra_data["attempt"], 3);
+/// }
+/// ```
+///
+/// Records are reported explicitly, so they are reported with implicit reporting
+/// turned off, and are never counted as leaks:
+///
+/// ```rust
+/// use traceback_error::{assert_reported, leak, lifecycle, testing};
+///
+/// let logger = traceback_error::log_support::TracebackLogger::new(log::LevelFilter::Error);
+/// log::set_boxed_logger(Box::new(logger)).unwrap();
+/// log::set_max_level(log::LevelFilter::Error);
+///
+/// lifecycle::set_implicit_reporting(false);
+/// let _capture = testing::capture();
+/// let leaks = leak::track();
+///
+/// log::error!("Connection refused");
+///
+/// assert_reported!(level = Error, message = "Connection refused");
+/// assert!(leaks.finish().is_empty());
+/// ```
+pub struct TracebackLogger {
+    level: LevelFilter,
+    inner: Option<Box<dyn Log>>,
+}
+
+impl TracebackLogger {
+    /// Reports records at or above `level`.
+    pub fn new(level: LevelFilter) -> Self {
+        Self { level, inner: None }
+    }
+
+    /// Passes every record on to `inner` after reporting it.
+    pub fn with_inner(mut self, inner: Box<dyn Log>) -> Self {
+        self.inner = Some(inner);
+        self
+    }
+}
+
+impl Default for TracebackLogger {
+    fn default() -> Self {
+        Self::new(LevelFilter::Error)
+    }
+}
+
+impl Log for TracebackLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+            || self
+                .inner
+                .as_ref()
+                .is_some_and(|inner| inner.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.level && record.target() != TARGET {
+            let mut fields = KeyValueVisitor::default();
+            // The visitor never fails
+            let _ = record.key_values().visit(&mut fields);
+            let mut error = TracebackError::new(
+                record.args().to_string(),
+                record.file().unwrap_or_default().to_string(),
+                record.line().unwrap_or_default(),
+                record.level().into(),
+            )
+            .with_extra_data(Value::Object(fields.fields));
+            error.module_path = record.module_path().map(str::to_string);
+            error.report();
+        }
+        if let Some(inner) = &self.inner {
+            inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(inner) = &self.inner {
+            inner.flush();
+        }
+    }
+}
+
+// Collects the key-values of a record, keeping numbers and booleans as such
+#[derive(Default)]
+struct KeyValueVisitor {
+    fields: Map<String, Value>,
+}
+
+impl<'kvs> VisitSource<'kvs> for KeyValueVisitor {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_bool() {
+            json!(value)
+        } else if let Some(value) = value.to_i64() {
+            json!(value)
+        } else if let Some(value) = value.to_u64() {
+            json!(value)
+        } else if let Some(value) = value.to_f64() {
+            json!(value)
+        } else {
+            json!(value.to_string())
+        };
+        self.fields.insert(key.to_string(), value);
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use serde_json::{Map, Value};
+use std::sync::Mutex;
+
+static DEFAULT_POLICY: Mutex<MergePolicy> = Mutex::new(MergePolicy::Deep);
+
+/// How new values are merged into existing `extra_data`.
+///
+/// Every policy except `Replace` merges objects found under the same key recursively,
+/// and only differs in what happens when other values meet.
+///
+/// ```rust
+/// use traceback_error::{merge::MergePolicy, serde_json::json, traceback};
+///
+/// let mut error = traceback!("Batch failed")
+///     .with_extra_data(json!({ "failed_ids": [1, 2], "batch": { "size": 10 } }))
+///     .with_extra_data_using(MergePolicy::Append, json!({ "failed_ids": [3] }))
+///     .with_extra_data_using(MergePolicy::KeepFirst, json!({ "batch": { "size": 20, "retry": true } }));
+///
+/// assert_eq!(error.extra_data["failed_ids"], json!([1, 2, 3]));
+/// assert_eq!(error.extra_data["batch"], json!({ "size": 10, "retry": true }));
+/// error.is_handled = true;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Any new value that is not an object replaces the old one. The default.
+    #[default]
+    Deep,
+    /// New top-level values replace old ones as a whole, without merging objects.
+    Replace,
+    /// Like `Deep`, but arrays are concatenated.
+    Append,
+    /// Like `Deep`, but the items of new arrays missing from old arrays are added.
+    Union,
+    /// Old values are kept, so only keys that are not set yet are added.
+    KeepFirst,
+    /// Like `KeepFirst`, but a key set to two different values is a conflict.
+    /// `with_extra_data_using` records conflicts in the error's metadata under
+    /// `extra_data_conflicts`, and `try_merge_extra_data` refuses to merge.
+    ErrorOnConflict,
+}
+
+/// Sets the policy `with_extra_data` and the `context` module merge with.
+pub fn set_default_merge_policy(policy: MergePolicy) {
+    *DEFAULT_POLICY.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Returns the policy `with_extra_data` and the `context` module merge with.
+pub fn default_merge_policy() -> MergePolicy {
+    *DEFAULT_POLICY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Merges `new` into `target` with the given policy, and returns the dot-separated
+/// paths of the keys that conflicted under `ErrorOnConflict`.
+pub(crate) fn merge(
+    target: &mut Map<String, Value>,
+    new: Map<String, Value>,
+    policy: MergePolicy,
+) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    merge_at(target, new, policy, "", &mut conflicts);
+    conflicts
+}
+
+fn merge_at(
+    target: &mut Map<String, Value>,
+    new: Map<String, Value>,
+    policy: MergePolicy,
+    prefix: &str,
+    conflicts: &mut Vec<String>,
+) {
+    for (key, new_value) in new {
+        let old_value = match target.get_mut(&key) {
+            Some(old_value) => old_value,
+            None => {
+                target.insert(key, new_value);
+                continue;
+            }
+        };
+        let path = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match (policy, old_value, new_value) {
+            (MergePolicy::Replace, old_value, new_value) => *old_value = new_value,
+            (_, Value::Object(old_obj), Value::Object(new_obj)) => {
+                merge_at(old_obj, new_obj, policy, &path, conflicts)
+            }
+            (MergePolicy::Append, Value::Array(old_items), Value::Array(new_items)) => {
+                old_items.extend(new_items)
+            }
+            (MergePolicy::Union, Value::Array(old_items), Value::Array(new_items)) => {
+                for item in new_items {
+                    if !old_items.contains(&item) {
+                        old_items.push(item);
+                    }
+                }
+            }
+            (MergePolicy::KeepFirst, _, _) => {}
+            (MergePolicy::ErrorOnConflict, old_value, new_value) => {
+                if *old_value != new_value {
+                    conflicts.push(path);
+                }
+            }
+            (_, old_value, new_value) => *old_value = new_value,
+        }
+    }
+}
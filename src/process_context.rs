@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::Instant,
+};
+
+static CAPTURE_PROCESS_CONTEXT: AtomicBool = AtomicBool::new(false);
+static REDACT_ARGS: AtomicBool = AtomicBool::new(false);
+static FALLBACK_START: OnceLock<Instant> = OnceLock::new();
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Information about the process and thread a `TracebackError` was created on.
+///
+/// Capturing is opt-in, as it touches the filesystem and exposes the command line.
+/// Enable it with `set_capture_process_context(true)`, after which every new
+/// `TracebackError` gets its `process` field filled when it is created.
+///
+/// ```rust
+/// traceback_error::process_context::set_capture_process_context(true);
+/// traceback_error::process_context::set_redact_args(true);
+///
+/// let mut error = traceback_error::traceback!("Something went wrong");
+/// let process = error.process.as_ref().unwrap();
+/// assert_eq!(process.pid, std::process::id());
+/// error.is_handled = true;
+///
+/// // Run this example again with arguments, and print the ones it captures
+/// if std::env::args().len() > 1 {
+///     println!("{}", process.args[1..].join(" "));
+///     return;
+/// }
+/// let output = std::process::Command::new(std::env::current_exe().unwrap())
+///     .args(["--token", "secret"])
+///     .output()
+///     .unwrap();
+/// assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "[REDACTED] [REDACTED]");
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProcessContext {
+    pub pid: u32,
+    pub thread_id: String,
+    pub thread_name: Option<String>,
+    pub executable: Option<String>,
+    pub args: Vec<String>,
+    pub working_dir: Option<String>,
+    pub uptime_ms: Option<u64>,
+}
+
+impl ProcessContext {
+    /// Captures the context of the current process and thread.
+    ///
+    /// If argument redaction is enabled, every argument except the program name is
+    /// replaced with `[REDACTED]`, so the argument count is kept but not the values.
+    pub fn capture() -> Self {
+        let thread = std::thread::current();
+        let redact = REDACT_ARGS.load(Ordering::Relaxed);
+        let args = std::env::args_os()
+            .enumerate()
+            .map(|(i, arg)| {
+                if redact && i > 0 {
+                    REDACTED.to_string()
+                } else {
+                    arg.to_string_lossy().into_owned()
+                }
+            })
+            .collect();
+        Self {
+            pid: std::process::id(),
+            thread_id: format!("{:?}", thread.id()),
+            thread_name: thread.name().map(|n| n.to_string()),
+            executable: std::env::current_exe()
+                .ok()
+                .map(|p| p.to_string_lossy().into_owned()),
+            args,
+            working_dir: std::env::current_dir()
+                .ok()
+                .map(|p| p.to_string_lossy().into_owned()),
+            uptime_ms: process_uptime_ms(),
+        }
+    }
+
+    /// Captures the context if `set_capture_process_context(true)` has been called.
+    pub fn capture_if_enabled() -> Option<Self> {
+        if CAPTURE_PROCESS_CONTEXT.load(Ordering::Relaxed) {
+            Some(Self::capture())
+        } else {
+            None
+        }
+    }
+}
+
+/// Enables or disables capturing a `ProcessContext` for every new `TracebackError`.
+pub fn set_capture_process_context(enabled: bool) {
+    if enabled {
+        FALLBACK_START.get_or_init(Instant::now);
+    }
+    CAPTURE_PROCESS_CONTEXT.store(enabled, Ordering::Relaxed);
+}
+
+/// Enables or disables redaction of command-line arguments in captured contexts.
+pub fn set_redact_args(redact: bool) {
+    REDACT_ARGS.store(redact, Ordering::Relaxed);
+}
+
+// On Linux the uptime is read from /proc. Elsewhere, it is measured from the moment
+// process context capturing was first enabled.
+fn process_uptime_ms() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(uptime) = proc_uptime_ms() {
+            return Some(uptime);
+        }
+    }
+    FALLBACK_START
+        .get()
+        .map(|start| start.elapsed().as_millis() as u64)
+}
+
+#[cfg(target_os = "linux")]
+fn proc_uptime_ms() -> Option<u64> {
+    // The kernel reports process start times in USER_HZ ticks, which is 100 on Linux
+    const TICKS_PER_SECOND: f64 = 100.0;
+
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // The command name may contain spaces, so start parsing after its closing paren.
+    // The first field after it is field 3 (state); starttime is field 22.
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    let start_ticks: f64 = after_comm.split_whitespace().nth(19)?.parse().ok()?;
+
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let system_uptime: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+
+    let process_uptime = system_uptime - start_ticks / TICKS_PER_SECOND;
+    Some((process_uptime.max(0.0) * 1000.0) as u64)
+}
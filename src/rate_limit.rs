@@ -0,0 +1,354 @@
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{dispatch, id::random_u64, timer::Timer, ErrorLevel, TracebackError};
+
+/// A token bucket limit: up to `burst` errors at once, refilled at `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        Self { per_second, burst }
+    }
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self) -> bool {
+        let elapsed = self.last_refill.elapsed().as_secs_f64();
+        self.last_refill = Instant::now();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst as f64);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Default)]
+struct Suppressed {
+    rate_limited: u64,
+    sampled: u64,
+}
+
+struct LimiterState {
+    global: Option<Bucket>,
+    per_level: HashMap<ErrorLevel, Bucket>,
+    sample_rates: HashMap<ErrorLevel, f64>,
+    suppressed: HashMap<ErrorLevel, Suppressed>,
+    report_interval: Duration,
+    last_report: Instant,
+    since: DateTime<Utc>,
+}
+
+impl LimiterState {
+    fn new() -> Self {
+        Self {
+            global: None,
+            per_level: HashMap::new(),
+            sample_rates: HashMap::new(),
+            suppressed: HashMap::new(),
+            report_interval: DEFAULT_REPORT_INTERVAL,
+            last_report: Instant::now(),
+            since: Utc::now(),
+        }
+    }
+
+    fn allows(&mut self, level: &ErrorLevel) -> bool {
+        if let Some(&rate) = self.sample_rates.get(level) {
+            if (random_u64() as f64 / u64::MAX as f64) >= rate {
+                self.suppressed.entry(level.clone()).or_default().sampled += 1;
+                return false;
+            }
+        }
+        let level_allows = match self.per_level.get_mut(level) {
+            Some(bucket) => bucket.try_take(),
+            None => true,
+        };
+        let allowed = level_allows
+            && match self.global.as_mut() {
+                Some(bucket) => bucket.try_take(),
+                None => true,
+            };
+        if !allowed {
+            self.suppressed
+                .entry(level.clone())
+                .or_default()
+                .rate_limited += 1;
+        }
+        allowed
+    }
+
+    // Builds the synthetic report for everything suppressed since the last one
+    fn take_report(&mut self) -> Option<TracebackError> {
+        self.last_report = Instant::now();
+        let since = std::mem::replace(&mut self.since, Utc::now());
+        let suppressed = std::mem::take(&mut self.suppressed);
+        let total: u64 = suppressed
+            .values()
+            .map(|s| s.rate_limited + s.sampled)
+            .sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut by_level = Map::new();
+        for (level, counts) in suppressed {
+            by_level.insert(
+                format!("{:?}", level),
+                json!({
+                    "rate_limited": counts.rate_limited,
+                    "sampled": counts.sampled,
+                }),
+            );
+        }
+        let mut report = TracebackError::new(
+            format!(
+                "{} errors were suppressed by rate limiting and sampling",
+                total
+            ),
+            file!().to_string(),
+            line!(),
+            ErrorLevel::Warn,
+        );
+        report
+            .metadata
+            .insert("suppressed_count".to_string(), json!(total));
+        report
+            .metadata
+            .insert("suppressed_by_level".to_string(), Value::Object(by_level));
+        report
+            .metadata
+            .insert("suppressed_since".to_string(), json!(since.to_rfc3339()));
+        report.is_handled = true;
+        Some(report)
+    }
+}
+
+const DEFAULT_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+static STATE: Mutex<Option<LimiterState>> = Mutex::new(None);
+
+static TIMER: Timer = Timer::new("traceback-rate-limit", next_deadline, report_due);
+
+fn with_state<T>(f: impl FnOnce(&mut LimiterState) -> T) -> T {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(state.get_or_insert_with(LimiterState::new))
+}
+
+/// Limits how many errors reach the callback across all levels. `None` removes the limit.
+///
+/// ```rust
+/// use traceback_error::{rate_limit::*, ErrorLevel};
+///
+/// // At most 10 errors per second, with bursts of up to 50
+/// set_global_rate_limit(Some(RateLimit::new(10.0, 50)));
+/// // At most one debug error per second
+/// set_level_rate_limit(ErrorLevel::Debug, Some(RateLimit::new(1.0, 1)));
+/// // Only report one in a hundred log-level errors
+/// set_sample_rate(ErrorLevel::Log, Some(0.01));
+/// ```
+///
+/// Errors over the limit are counted, and the count is reported later:
+///
+/// ```rust
+/// use traceback_error::{rate_limit::*, testing, traceback};
+///
+/// // Bursts of 2, refilled once every 1000 seconds
+/// set_global_rate_limit(Some(RateLimit::new(0.001, 2)));
+/// let capture = testing::capture();
+/// for attempt in 0..10 {
+///     traceback!(format!("attempt {} failed", attempt)).report();
+/// }
+/// assert_eq!(capture.errors().len(), 2);
+///
+/// flush_suppressed();
+/// let errors = capture.take();
+/// assert_eq!(errors.len(), 3);
+/// assert_eq!(errors[2].metadata["suppressed_count"], 8);
+/// ```
+pub fn set_global_rate_limit(limit: Option<RateLimit>) {
+    with_state(|state| state.global = limit.map(Bucket::new));
+}
+
+/// Limits how many errors of the given level reach the callback. `None` removes the limit.
+pub fn set_level_rate_limit(level: ErrorLevel, limit: Option<RateLimit>) {
+    with_state(|state| match limit {
+        Some(limit) => {
+            state.per_level.insert(level, Bucket::new(limit));
+        }
+        None => {
+            state.per_level.remove(&level);
+        }
+    });
+}
+
+/// Only lets the given fraction (between `0.0` and `1.0`) of the errors of a level
+/// through, chosen at random. `None` reports every error of the level.
+pub fn set_sample_rate(level: ErrorLevel, rate: Option<f64>) {
+    with_state(|state| match rate {
+        Some(rate) => {
+            state.sample_rates.insert(level, rate.clamp(0.0, 1.0));
+        }
+        None => {
+            state.sample_rates.remove(&level);
+        }
+    });
+}
+
+/// Sets how often the number of suppressed errors is reported. Defaults to a minute.
+///
+/// The report is a synthetic `TracebackError` at the `Warn` level, with the counts
+/// in its `metadata`, so that silence is never mistaken for health. It is sent from a
+/// background thread once the interval has passed, even if no further errors arrive.
+/// While a `testing::capture` is alive, the background thread waits, and the report
+/// goes out with the next error instead. Counts not yet reported when the program exits are lost, unless they are flushed,
+/// for example with a `flush_guard`.
+///
+/// ```rust
+/// use std::{sync::Mutex, thread, time::Duration};
+/// use traceback_error::{rate_limit::*, traceback, TracebackError};
+///
+/// static RECEIVED: Mutex<Vec<TracebackError>> = Mutex::new(Vec::new());
+///
+/// fn record(error: TracebackError) {
+///     RECEIVED.lock().unwrap().push(error);
+/// }
+///
+/// fn main() {
+///     traceback_error::set_traceback!(record);
+///     set_suppression_report_interval(Duration::from_millis(50));
+///     set_global_rate_limit(Some(RateLimit::new(0.001, 1)));
+///     for _ in 0..5 {
+///         traceback!("Disk full").report();
+///     }
+///
+///     thread::sleep(Duration::from_millis(500));
+///     let received = RECEIVED.lock().unwrap();
+///     assert_eq!(received.len(), 2);
+///     assert_eq!(received[1].metadata["suppressed_count"], 4);
+/// }
+/// ```
+///
+/// While capturing, a report that became due is captured with the next error:
+///
+/// ```rust
+/// use std::{thread, time::Duration};
+/// use traceback_error::{rate_limit::*, testing, traceback};
+///
+/// set_suppression_report_interval(Duration::from_millis(50));
+/// set_global_rate_limit(Some(RateLimit::new(0.001, 1)));
+/// let capture = testing::capture();
+/// for _ in 0..5 {
+///     traceback!("Disk full").report();
+/// }
+///
+/// thread::sleep(Duration::from_millis(200));
+/// assert_eq!(capture.take().len(), 1);
+///
+/// traceback!("Disk full").report();
+/// let errors = capture.take();
+/// assert_eq!(errors.len(), 1);
+/// assert_eq!(errors[0].metadata["suppressed_count"], 4);
+/// ```
+pub fn set_suppression_report_interval(interval: Duration) {
+    with_state(|state| state.report_interval = interval);
+    TIMER.wake();
+}
+
+/// Reports the errors suppressed so far right away, if there are any.
+pub fn flush_suppressed() {
+    if let Some(report) = with_state(|state| state.take_report()) {
+        dispatch::invoke_callback(report);
+    }
+}
+
+/// Reports the errors suppressed so far when dropped, see `flush_guard`.
+#[must_use = "the suppressed errors are reported when the guard is dropped"]
+pub struct FlushGuard {
+    _private: (),
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        flush_suppressed();
+    }
+}
+
+/// Returns a guard that calls `flush_suppressed` when dropped, such as at the end of
+/// `main`.
+///
+/// ```rust
+/// let _flush = traceback_error::rate_limit::flush_guard();
+/// // Errors suppressed but not yet reported when `_flush` goes out of scope are
+/// // reported then
+/// ```
+pub fn flush_guard() -> FlushGuard {
+    FlushGuard { _private: () }
+}
+
+// When the next suppression report is due, if anything was suppressed
+fn next_deadline() -> Option<Instant> {
+    with_state(|state| {
+        if state.suppressed.is_empty() {
+            None
+        } else {
+            Some(state.last_report + state.report_interval)
+        }
+    })
+}
+
+fn report_due() {
+    let report = with_state(|state| {
+        if state.last_report.elapsed() >= state.report_interval {
+            state.take_report()
+        } else {
+            None
+        }
+    });
+    if let Some(report) = report {
+        dispatch::invoke_callback(report);
+    }
+}
+
+/// Returns the errors that should reach the callback for the given error: the error
+/// itself unless it was suppressed, preceded by a suppression report if one is due.
+pub(crate) fn process(error: TracebackError) -> Vec<TracebackError> {
+    with_state(|state| {
+        let mut out = Vec::new();
+        if state.last_report.elapsed() >= state.report_interval {
+            out.extend(state.take_report());
+        }
+        let first_suppressed = state.suppressed.is_empty();
+        if state.allows(&error.level) {
+            out.push(error);
+        } else if first_suppressed {
+            TIMER.wake();
+        }
+        out
+    })
+}
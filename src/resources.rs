@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{enrich::TracebackEnricher, TracebackError};
+
+/// A snapshot of the resources used by the current process and the system.
+///
+/// The values are read from `/proc`, so they are only available on Linux. Any value
+/// that cannot be read is left as `None` rather than failing the whole snapshot.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SystemResources {
+    pub rss_bytes: Option<u64>,
+    pub open_fds: Option<u64>,
+    pub threads: Option<u64>,
+    pub load_average: Option<[f64; 3]>,
+    pub mem_free_bytes: Option<u64>,
+    pub mem_available_bytes: Option<u64>,
+}
+
+impl SystemResources {
+    /// Reads `/proc/self/status`, `/proc/self/fd`, `/proc/loadavg` and `/proc/meminfo`.
+    pub fn from_proc() -> Self {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let meminfo = std::fs::read_to_string("/proc/meminfo").unwrap_or_default();
+        Self {
+            rss_bytes: proc_field(&status, "VmRSS").map(|kb| kb * 1024),
+            // Reading the directory holds a descriptor of its own, which is not counted
+            open_fds: std::fs::read_dir("/proc/self/fd")
+                .ok()
+                .map(|entries| (entries.count() as u64).saturating_sub(1)),
+            threads: proc_field(&status, "Threads"),
+            load_average: read_load_average(),
+            mem_free_bytes: proc_field(&meminfo, "MemFree").map(|kb| kb * 1024),
+            mem_available_bytes: proc_field(&meminfo, "MemAvailable").map(|kb| kb * 1024),
+        }
+    }
+}
+
+/// An enricher that attaches a `SystemResources` snapshot to every error under the
+/// `resources` key of its `metadata`.
+///
+/// ```rust
+/// use traceback_error::{enrich, resources::SystemResourcesEnricher, testing, traceback};
+///
+/// enrich::add_enricher(SystemResourcesEnricher);
+/// let capture = testing::capture();
+/// traceback!("Out of memory").report();
+///
+/// let errors = capture.take();
+/// let resources = &errors[0].metadata["resources"];
+/// if cfg!(target_os = "linux") {
+///     assert!(resources["rss_bytes"].as_u64().unwrap() > 0);
+///     assert!(resources["threads"].as_u64().unwrap() >= 1);
+/// }
+/// ```
+pub struct SystemResourcesEnricher;
+
+impl TracebackEnricher for SystemResourcesEnricher {
+    fn enrich(&self, error: &mut TracebackError) {
+        if let Ok(resources) = serde_json::to_value(SystemResources::from_proc()) {
+            error.metadata.insert("resources".to_string(), resources);
+        }
+    }
+}
+
+// Parses lines like "VmRSS:	  12345 kB" and returns the first number
+fn proc_field(contents: &str, name: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim() != name {
+            return None;
+        }
+        value.split_whitespace().next()?.parse().ok()
+    })
+}
+
+fn read_load_average() -> Option<[f64; 3]> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+    let mut values = loadavg.split_whitespace().map(|v| v.parse::<f64>());
+    Some([
+        values.next()?.ok()?,
+        values.next()?.ok()?,
+        values.next()?.ok()?,
+    ])
+}
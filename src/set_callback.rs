@@ -53,10 +53,12 @@ pub fn reset_traceback_callback() {
 ///     println!("Custom traceback callback called: {:?}", error);
 /// }
 ///
-/// // Use the set_traceback macro to set the custom traceback callback
-/// traceback_error::set_traceback!(my_traceback_callback);
+/// fn main() {
+///     // Use the set_traceback macro to set the custom traceback callback
+///     traceback_error::set_traceback!(my_traceback_callback);
 ///
-/// // Any TracebackErrors will now be handled by my_traceback_callback when dropped
+///     // Any TracebackErrors will now be handled by my_traceback_callback when dropped
+/// }
 /// ```
 ///
 /// ```rust
@@ -66,8 +68,10 @@ pub fn reset_traceback_callback() {
 ///     println!("Async custom traceback callback called: {:?}", error);
 /// }
 ///
-/// // But you have to specify that it is asynchronous
-/// traceback_error::set_traceback!(async my_traceback_callback);
+/// fn main() {
+///     // But you have to specify that it is asynchronous
+///     traceback_error::set_traceback!(async my_traceback_callback);
+/// }
 /// ```
 #[macro_export]
 macro_rules! set_traceback {
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+/// A `tracing` span that was active when a `TracebackError` was created.
+///
+/// `fields` holds the span's fields as `tracing_subscriber` formats them, like
+/// `user_id=42 path="/users"`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SpanInfo {
+    pub name: String,
+    pub target: String,
+    pub fields: String,
+}
+
+/// Captures the spans active on the current thread, innermost first.
+///
+/// Requires the `tracing` feature, and a subscriber with a
+/// `tracing_error::ErrorLayer` installed. Otherwise, nothing is captured.
+///
+/// ```rust
+/// # #[cfg(feature = "tracing")]
+/// # {
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let subscriber = tracing_subscriber::registry().with(tracing_error::ErrorLayer::default());
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!("handle_request", user_id = 42).entered();
+///
+///     let mut error = traceback_error::traceback!("Request failed");
+///     assert_eq!(error.span_trace[0].name, "handle_request");
+///     assert_eq!(error.span_trace[0].fields, "user_id=42");
+///     error.is_handled = true;
+/// });
+/// # }
+/// ```
+pub fn capture() -> Vec<SpanInfo> {
+    #[cfg(feature = "tracing")]
+    {
+        let mut spans = Vec::new();
+        tracing_error::SpanTrace::capture().with_spans(|metadata, fields| {
+            spans.push(SpanInfo {
+                name: metadata.name().to_string(),
+                target: metadata.target().to_string(),
+                fields: fields.to_string(),
+            });
+            true
+        });
+        spans
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        Vec::new()
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{timer, ErrorLevel, TracebackError};
+
+thread_local! {
+    // One list of captured errors per live capture on this thread, innermost last
+    static CAPTURED: RefCell<Vec<Vec<TracebackError>>> = const { RefCell::new(Vec::new()) };
+}
+
+// Live captures across all threads
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Captures the errors reported on the current thread, until the returned guard is
+/// dropped.
+///
+/// While the guard is alive, errors that would reach the callback on this thread are
+/// collected instead. Captures are per thread, so tests running in parallel don't see
+/// each other's errors, and errors reported on other threads are not captured. Errors
+/// filtered out by level, or held back by deduplication or rate limiting, are not
+/// captured either. Dedup summaries and suppression reports are captured when they are
+/// flushed, or sent along with the next error reported on this thread. While any capture
+/// is alive, they are not sent from the background thread, so they never bypass it.
+/// Nested captures collect errors until they are dropped, after which the outer one
+/// collects them again.
+///
+/// The `assert_reported!` and `assert_no_errors!` macros check the innermost capture.
+///
+/// ```rust
+/// use traceback_error::{testing, traceback, ErrorLevel};
+///
+/// let capture = testing::capture();
+/// let mut error = traceback!("Request timed out");
+/// error.level = ErrorLevel::Warn;
+/// drop(error);
+///
+/// let errors = capture.errors();
+/// assert_eq!(errors.len(), 1);
+/// assert_eq!(errors[0].message, "Request timed out");
+/// ```
+///
+/// Only what the callback would receive is captured:
+///
+/// ```rust
+/// use std::time::Duration;
+/// use traceback_error::{dedup, testing, traceback};
+///
+/// dedup::set_dedup_window(Some(Duration::from_secs(60)));
+/// let capture = testing::capture();
+/// for _ in 0..3 {
+///     traceback!("Connection reset").report();
+/// }
+/// assert_eq!(capture.take().len(), 1);
+///
+/// // The summary of the two duplicates
+/// dedup::flush_dedup();
+/// let errors = capture.take();
+/// assert_eq!(errors.len(), 1);
+/// assert_eq!(errors[0].metadata["occurrence_count"], 3);
+/// ```
+pub fn capture() -> Capture {
+    CAPTURED.with(|captured| captured.borrow_mut().push(Vec::new()));
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    Capture { _private: () }
+}
+
+/// Collects the errors reported on the current thread while alive, see `capture`.
+#[must_use = "errors are only captured while the guard is alive"]
+pub struct Capture {
+    _private: (),
+}
+
+impl Capture {
+    /// Returns copies of the errors captured so far, oldest first.
+    pub fn errors(&self) -> Vec<TracebackError> {
+        with_innermost(|errors| errors.clone()).unwrap_or_default()
+    }
+
+    /// Removes and returns the errors captured so far, oldest first.
+    pub fn take(&self) -> Vec<TracebackError> {
+        with_innermost(std::mem::take).unwrap_or_default()
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        CAPTURED.with(|captured| captured.borrow_mut().pop());
+        if ACTIVE.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // Let the timers send what became due during the capture
+            timer::wake_all();
+        }
+    }
+}
+
+/// What `assert_reported` looks for in a captured error. Every criterion that is set
+/// has to match.
+#[derive(Debug, Clone, Default)]
+pub struct Expected {
+    pub level: Option<ErrorLevel>,
+    pub message: Option<String>,
+    pub message_contains: Option<String>,
+}
+
+impl Expected {
+    pub fn level(mut self, level: ErrorLevel) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    pub fn message_contains(mut self, message: &str) -> Self {
+        self.message_contains = Some(message.to_string());
+        self
+    }
+
+    pub fn matches(&self, error: &TracebackError) -> bool {
+        self.level
+            .as_ref()
+            .map_or(true, |level| error.level == *level)
+            && self
+                .message
+                .as_ref()
+                .map_or(true, |message| error.message == *message)
+            && self
+                .message_contains
+                .as_ref()
+                .map_or(true, |message| error.message.contains(message.as_str()))
+    }
+}
+
+/// Panics unless the innermost capture on this thread holds an error matching
+/// `expected`, and returns a copy of the first one that does.
+#[track_caller]
+pub fn assert_reported(expected: Expected) -> TracebackError {
+    let errors = captured_errors();
+    match errors.iter().find(|error| expected.matches(error)) {
+        Some(error) => error.clone(),
+        None => panic!(
+            "no captured error matches {:?}, captured: {:?}",
+            expected,
+            messages(&errors)
+        ),
+    }
+}
+
+/// Panics if the innermost capture on this thread holds any errors.
+#[track_caller]
+pub fn assert_no_errors() {
+    let errors = captured_errors();
+    if !errors.is_empty() {
+        panic!("expected no errors, captured: {:?}", messages(&errors));
+    }
+}
+
+#[track_caller]
+fn captured_errors() -> Vec<TracebackError> {
+    match with_innermost(|errors| errors.clone()) {
+        Some(errors) => errors,
+        None => panic!("no capture is active on this thread, call `testing::capture` first"),
+    }
+}
+
+fn messages(errors: &[TracebackError]) -> Vec<&str> {
+    errors.iter().map(|error| error.message.as_str()).collect()
+}
+
+fn with_innermost<T>(f: impl FnOnce(&mut Vec<TracebackError>) -> T) -> Option<T> {
+    CAPTURED.with(|captured| captured.borrow_mut().last_mut().map(f))
+}
+
+/// Whether a capture is alive on any thread.
+pub(crate) fn active() -> bool {
+    ACTIVE.load(Ordering::SeqCst) > 0
+}
+
+/// Keeps a dispatched error if a capture is active on this thread, or gives it back.
+pub(crate) fn intercept(error: TracebackError) -> Option<TracebackError> {
+    CAPTURED.with(|captured| match captured.try_borrow_mut() {
+        Ok(mut captured) => match captured.last_mut() {
+            Some(errors) => {
+                errors.push(error);
+                None
+            }
+            None => Some(error),
+        },
+        // Dispatched while the captured errors are borrowed, by a dropped copy
+        Err(_) => Some(error),
+    })
+}
+
+/// Asserts that an error matching the given criteria was captured on this thread, and
+/// evaluates to a copy of it.
+///
+/// The criteria are `level`, which takes a variant name such as `Warn` or an
+/// `ErrorLevel` expression such as a variable, `message` for the exact message, and
+/// `message_contains`.
+///
+/// ```rust
+/// use traceback_error::{assert_no_errors, assert_reported, testing, traceback, ErrorLevel};
+///
+/// let _capture = testing::capture();
+/// assert_no_errors!();
+///
+/// let mut error = traceback!("Upstream timeout after 30s");
+/// error.level = ErrorLevel::Warn;
+/// error.report();
+///
+/// assert_reported!(level = Warn, message_contains = "timeout");
+/// assert_reported!(level = ErrorLevel::Warn,);
+/// let expected = ErrorLevel::Warn;
+/// let error = assert_reported!(level = expected, message = "Upstream timeout after 30s");
+/// assert_eq!(error.level, ErrorLevel::Warn);
+/// ```
+#[macro_export]
+macro_rules! assert_reported {
+    ($($criteria:tt)*) => {
+        $crate::testing::assert_reported($crate::__expected!(
+            $crate::testing::Expected::default();
+            $($criteria)*
+        ))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __expected {
+    ($expected:expr;) => {
+        $expected
+    };
+    ($expected:expr; level = $level:ident $(, $($rest:tt)*)?) => {
+        $crate::__expected!($expected.level($crate::__level!($level)); $($($rest)*)?)
+    };
+    ($expected:expr; $key:ident = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::__expected!($expected.$key($value); $($($rest)*)?)
+    };
+}
+
+// A bare variant name, or else a variable holding an `ErrorLevel`
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __level {
+    (None) => {
+        $crate::ErrorLevel::None
+    };
+    (Unknown) => {
+        $crate::ErrorLevel::Unknown
+    };
+    (Log) => {
+        $crate::ErrorLevel::Log
+    };
+    (Debug) => {
+        $crate::ErrorLevel::Debug
+    };
+    (Warn) => {
+        $crate::ErrorLevel::Warn
+    };
+    (Error) => {
+        $crate::ErrorLevel::Error
+    };
+    (Critical) => {
+        $crate::ErrorLevel::Critical
+    };
+    (Fatal) => {
+        $crate::ErrorLevel::Fatal
+    };
+    ($level:ident) => {
+        $level
+    };
+}
+
+/// Asserts that no errors were captured on this thread.
+///
+/// ```rust
+/// let _capture = traceback_error::testing::capture();
+/// traceback_error::traceback!("Expected failure").handled(None);
+/// traceback_error::assert_no_errors!();
+/// ```
+#[macro_export]
+macro_rules! assert_no_errors {
+    () => {
+        $crate::testing::assert_no_errors()
+    };
+}
@@ -0,0 +1,79 @@
+use std::{
+    sync::{Mutex, OnceLock},
+    thread::{self, Thread},
+    time::Instant,
+};
+
+use crate::testing;
+
+// Every timer thread started so far
+static THREADS: Mutex<Vec<Thread>> = Mutex::new(Vec::new());
+
+/// A background thread that runs `fire` whenever the deadline returned by `next` has
+/// passed, so pending reports go out even when no further errors arrive.
+///
+/// The thread waits while a `testing::capture` is active on any thread, as what it
+/// reports would bypass the capture. The capturing thread picks up what is due with
+/// its next report or flush instead.
+pub(crate) struct Timer {
+    name: &'static str,
+    next: fn() -> Option<Instant>,
+    fire: fn(),
+    thread: OnceLock<Thread>,
+}
+
+impl Timer {
+    pub(crate) const fn new(name: &'static str, next: fn() -> Option<Instant>, fire: fn()) -> Self {
+        Self {
+            name,
+            next,
+            fire,
+            thread: OnceLock::new(),
+        }
+    }
+
+    /// Makes the thread look at the deadline again, starting it if needed.
+    pub(crate) fn wake(&'static self) {
+        let thread = self.thread.get_or_init(|| {
+            let thread = thread::Builder::new()
+                .name(self.name.to_string())
+                .spawn(move || self.run())
+                .expect("failed to spawn timer thread")
+                .thread()
+                .clone();
+            THREADS
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(thread.clone());
+            thread
+        });
+        thread.unpark();
+    }
+
+    fn run(&self) {
+        loop {
+            if testing::active() {
+                thread::park();
+                continue;
+            }
+            match (self.next)() {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        (self.fire)();
+                    } else {
+                        thread::park_timeout(deadline - now);
+                    }
+                }
+                None => thread::park(),
+            }
+        }
+    }
+}
+
+/// Makes every timer thread look at its deadline again.
+pub(crate) fn wake_all() {
+    for thread in THREADS.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+        thread.unpark();
+    }
+}
@@ -0,0 +1,327 @@
+use serde_json::{json, Map, Value};
+use std::fmt::Debug;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{
+    fmt::{format::DefaultFields, FormattedFields},
+    layer::Context,
+    registry::LookupSpan,
+    Layer,
+};
+
+use crate::{set_callback::TracebackCallback, span_trace::SpanInfo, TracebackError};
+
+/// The target of the events `TracingSink` emits. `TracebackLayer` ignores events with
+/// this target, so it never reports the sink's own events again.
+pub const TARGET: &str = "traceback_error";
+
+/// Collects the fields of a `tracing` event or span, keeping the `message` field apart.
+#[derive(Default)]
+pub(crate) struct FieldVisitor {
+    pub(crate) message: Option<String>,
+    pub(crate) fields: Map<String, Value>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.fields.insert(field.name().to_string(), json!(value));
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.fields
+            .insert(field.name().to_string(), json!(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        let value = format!("{:?}", value);
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.insert(field.name().to_string(), json!(value));
+        }
+    }
+}
+
+/// A callback that emits handled errors as `tracing` events, at the `tracing` level
+/// their `ErrorLevel` maps to.
+///
+/// The event's message is the error message, and its fields hold the id, reference,
+/// fingerprint, location, the names of the captured spans and the extra data.
+///
+/// Errors reported by a `TracebackLayer` are reported while the subscriber is handling
+/// an event. `tracing` drops events emitted at that point under a scoped subscriber,
+/// like one set with `tracing::subscriber::with_default`, so those errors only reach
+/// a `TracingSink` when the subscriber is the global default.
+///
+/// ```rust
+/// use std::{
+///     collections::HashMap,
+///     sync::{Arc, Mutex},
+/// };
+/// use tracing::{
+///     field::{Field, Visit},
+///     Event, Level, Subscriber,
+/// };
+/// use tracing_subscriber::{
+///     layer::{Context, SubscriberExt},
+///     Layer,
+/// };
+/// use traceback_error::{
+///     serde_json::json,
+///     set_callback::{set_traceback_callback, TracebackCallbackType},
+///     tracing_support::TracingSink,
+///     ErrorLevel,
+/// };
+///
+/// #[derive(Default)]
+/// struct Fields(HashMap<String, String>);
+///
+/// impl Visit for Fields {
+///     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+///         self.0.insert(field.name().to_string(), format!("{:?}", value));
+///     }
+/// }
+///
+/// // Records the level, target and fields of every event
+/// #[derive(Clone, Default)]
+/// struct Recorder(Arc<Mutex<Vec<(Level, String, HashMap<String, String>)>>>);
+///
+/// impl<S: Subscriber> Layer<S> for Recorder {
+///     fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+///         let mut fields = Fields::default();
+///         event.record(&mut fields);
+///         let metadata = event.metadata();
+///         let record = (*metadata.level(), metadata.target().to_string(), fields.0);
+///         self.0.lock().unwrap().push(record);
+///     }
+/// }
+///
+/// set_traceback_callback(TracebackCallbackType::Sync(Box::new(TracingSink)));
+///
+/// let recorder = Recorder::default();
+/// let subscriber = tracing_subscriber::registry().with(recorder.clone());
+/// tracing::subscriber::with_default(subscriber, || {
+///     let mut error = traceback_error::traceback!("Query failed")
+///         .with_extra_data(json!({ "table": "users" }));
+///     error.level = ErrorLevel::Warn;
+///     drop(error);
+/// });
+///
+/// let events = recorder.0.lock().unwrap();
+/// let (level, target, fields) = &events[0];
+/// assert_eq!(*level, Level::WARN);
+/// assert_eq!(target, "traceback_error");
+/// assert_eq!(fields["message"], "Query failed");
+/// assert_eq!(fields["extra_data"], r#"{"table":"users"}"#);
+/// ```
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingSink;
+
+impl TracebackCallback for TracingSink {
+    fn call(&self, error: TracebackError) {
+        let fingerprint = error.fingerprint().to_string();
+        let module_path = error.module_path.as_deref().unwrap_or_default();
+        let spans = error
+            .span_trace
+            .iter()
+            .map(|span| span.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" < ");
+        let extra_data = Value::Object(error.extra_data.clone());
+        // `event!` needs the level as a constant
+        macro_rules! emit {
+            ($level:expr) => {
+                tracing::event!(
+                    target: TARGET,
+                    $level,
+                    id = %error.id,
+                    reference = %error.reference(),
+                    fingerprint = %fingerprint,
+                    file = %error.file,
+                    line = error.line,
+                    module_path = %module_path,
+                    spans = %spans,
+                    extra_data = %extra_data,
+                    "{}",
+                    error.message
+                )
+            };
+        }
+        match Level::from(&error.level) {
+            Level::ERROR => emit!(Level::ERROR),
+            Level::WARN => emit!(Level::WARN),
+            Level::INFO => emit!(Level::INFO),
+            Level::DEBUG => emit!(Level::DEBUG),
+            _ => emit!(Level::TRACE),
+        }
+    }
+}
+
+/// A `tracing_subscriber::Layer` that reports events carrying an error field as
+/// `TracebackError`s.
+///
+/// By default, `ERROR` events with a field named `error` are reported. The field's
+/// value becomes the error message, the event's location its file and line, and the
+/// remaining fields its extra data, with the event's own message under
+/// `event_message`. The spans the event happened in are captured into `span_trace`.
+///
+/// The errors are reported from within the subscriber, so a `TracingSink` only receives
+/// them when the subscriber is the global default, see `TracingSink`.
+///
+/// ```rust
+/// use std::sync::Mutex;
+/// use tracing_subscriber::layer::SubscriberExt;
+/// use traceback_error::TracebackError;
+///
+/// static REPORTED: Mutex<Vec<TracebackError>> = Mutex::new(Vec::new());
+///
+/// fn record(error: TracebackError) {
+///     REPORTED.lock().unwrap().push(error);
+/// }
+///
+/// fn main() {
+///     traceback_error::set_traceback!(record);
+///
+///     let subscriber = tracing_subscriber::registry()
+///         .with(tracing_error::ErrorLayer::default())
+///         .with(traceback_error::tracing_support::TracebackLayer::new());
+///     tracing::subscriber::with_default(subscriber, || {
+///         let _span = tracing::info_span!("save", path = "/tmp/out").entered();
+///         let error = std::io::Error::new(std::io::ErrorKind::Other, "disk full");
+///         tracing::error!(error = &error as &dyn std::error::Error, "saving failed");
+///     });
+///
+///     let reported = REPORTED.lock().unwrap();
+///     assert_eq!(reported[0].message, "disk full");
+///     assert_eq!(reported[0].extra_data["event_message"], "saving failed");
+///     assert_eq!(reported[0].span_trace[0].name, "save");
+/// }
+/// ```
+///
+/// Events are reported explicitly, so they are reported with implicit reporting
+/// turned off, and are never counted as leaks:
+///
+/// ```rust
+/// use tracing_subscriber::layer::SubscriberExt;
+/// use traceback_error::{assert_reported, leak, lifecycle, testing};
+///
+/// lifecycle::set_implicit_reporting(false);
+/// let _capture = testing::capture();
+/// let leaks = leak::track();
+///
+/// let subscriber = tracing_subscriber::registry()
+///     .with(traceback_error::tracing_support::TracebackLayer::new());
+/// tracing::subscriber::with_default(subscriber, || {
+///     let error = std::io::Error::new(std::io::ErrorKind::Other, "disk full");
+///     tracing::error!(error = &error as &dyn std::error::Error, "saving failed");
+/// });
+///
+/// assert_reported!(level = Error, message = "disk full");
+/// assert!(leaks.finish().is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TracebackLayer {
+    level: Level,
+    field: &'static str,
+}
+
+impl TracebackLayer {
+    pub fn new() -> Self {
+        Self {
+            level: Level::ERROR,
+            field: "error",
+        }
+    }
+
+    /// Reports events at or above `level`, instead of only `ERROR` events.
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Looks for the error in the field with the given name, instead of `error`.
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        self.field = field;
+        self
+    }
+}
+
+impl Default for TracebackLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for TracebackLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // tracing orders levels by verbosity, so more severe levels compare as smaller
+        if *metadata.level() > self.level || metadata.target() == TARGET {
+            return;
+        }
+        let mut fields = FieldVisitor::default();
+        event.record(&mut fields);
+        let message = match fields.fields.remove(self.field) {
+            Some(Value::String(message)) => message,
+            Some(value) => value.to_string(),
+            None => return,
+        };
+        if let Some(event_message) = fields.message {
+            fields
+                .fields
+                .insert("event_message".to_string(), json!(event_message));
+        }
+        let mut error = TracebackError::new(
+            message,
+            metadata.file().unwrap_or_default().to_string(),
+            metadata.line().unwrap_or_default(),
+            (*metadata.level()).into(),
+        )
+        .with_extra_data(Value::Object(fields.fields));
+        error.module_path = metadata.module_path().map(str::to_string);
+        // The dispatcher is busy with this event, so `SpanTrace::capture` would come up
+        // empty here; read the spans from the registry instead
+        error.span_trace = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .map(|span| SpanInfo {
+                        name: span.name().to_string(),
+                        target: span.metadata().target().to_string(),
+                        fields: span
+                            .extensions()
+                            .get::<FormattedFields<DefaultFields>>()
+                            .map(|formatted| formatted.fields.clone())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        error.report();
+    }
+}
@@ -0,0 +1,153 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{
+    meta::ParseNestedMeta, parenthesized, parse::Parse, parse::ParseStream, punctuated::Punctuated,
+    Error, Expr, Ident, ItemFn, LitStr, Result, ReturnType, Token,
+};
+
+use crate::level;
+
+/// The options of the `instrument` attribute.
+#[derive(Default)]
+pub struct Options {
+    fields: Vec<Field>,
+    message: Option<LitStr>,
+    level: Option<TokenStream>,
+}
+
+impl Options {
+    pub fn parse(&mut self, meta: ParseNestedMeta) -> Result<()> {
+        if meta.path.is_ident("fields") {
+            let content;
+            parenthesized!(content in meta.input);
+            self.fields
+                .extend(Punctuated::<Field, Token![,]>::parse_terminated(&content)?);
+        } else if meta.path.is_ident("message") {
+            self.message = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("level") {
+            self.level = Some(level::known(&meta.value()?.parse()?)?);
+        } else if meta.path.is_ident("custom_level") {
+            self.level = Some(level::custom(&meta.value()?.parse()?)?);
+        } else {
+            return Err(meta.error("expected `fields`, `message`, `level` or `custom_level`"));
+        }
+        Ok(())
+    }
+}
+
+// `name`, recording the argument of that name, or `name = expression`
+struct Field {
+    name: Ident,
+    value: Option<Expr>,
+}
+
+impl Parse for Field {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(Field { name, value })
+    }
+}
+
+pub fn expand(options: Options, item: ItemFn) -> Result<TokenStream> {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    let output = match &sig.output {
+        ReturnType::Type(_, output) => output,
+        ReturnType::Default => {
+            return Err(Error::new_spanned(
+                &sig,
+                "`instrument` requires a function returning `Result<_, TracebackError>`",
+            ))
+        }
+    };
+
+    let function = sig.ident.to_string();
+    let inserts = options.fields.iter().map(|field| {
+        let key = field.name.to_string();
+        let name = &field.name;
+        let value = match &field.value {
+            Some(value) => quote!(#value),
+            None => quote!(#name),
+        };
+        quote! {
+            __traceback_fields.insert(
+                #key.to_string(),
+                ::traceback_error::serde_json::to_value(&#value).unwrap_or_else(|e| {
+                    ::traceback_error::serde_json::Value::String(::std::format!(
+                        "failed to serialize: {}",
+                        e
+                    ))
+                }),
+            );
+        }
+    });
+    let message = match &options.message {
+        Some(message) => quote!(#message.to_string()),
+        None => {
+            let message = format!("{} failed", function);
+            quote!(#message.to_string())
+        }
+    };
+    let level = match &options.level {
+        Some(level) => level.clone(),
+        None => quote!(__traceback_error.level.clone()),
+    };
+    // The body runs in a closure or async block, so that `return` and `?` leave it
+    // rather than the function. The locals around it are prefixed, so the body cannot
+    // see them in place of its own variables
+    let result = if sig.asyncness.is_some() {
+        quote! {
+            async move {
+                let result: #output = #block;
+                result
+            }
+            .await
+        }
+    } else {
+        quote!((move || -> #output #block)())
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            // Recorded first, as the body may move the arguments
+            let mut __traceback_fields = ::traceback_error::serde_json::Map::new();
+            __traceback_fields.insert(
+                "function".to_string(),
+                ::traceback_error::serde_json::json!(#function),
+            );
+            #(#inserts)*
+            #[allow(clippy::redundant_closure_call)]
+            let __traceback_result: #output = #result;
+            match __traceback_result {
+                ::std::result::Result::Ok(value) => ::std::result::Result::Ok(value),
+                ::std::result::Result::Err(__traceback_error) => {
+                    let level = #level;
+                    ::std::result::Result::Err(
+                        ::traceback_error::TracebackError::new(
+                            #message,
+                            ::core::file!().to_string(),
+                            ::core::line!(),
+                            level,
+                        )
+                        .with_module_path(::core::module_path!())
+                        .with_extra_data(::traceback_error::serde_json::Value::Object(
+                            __traceback_fields,
+                        ))
+                        .with_parent(__traceback_error),
+                    )
+                }
+            }
+        }
+    })
+}
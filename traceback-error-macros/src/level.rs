@@ -0,0 +1,51 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Error, LitStr, Result};
+
+// The names `ErrorLevel` parses to one of its own variants, ignoring case
+const KNOWN: &[(&str, &str)] = &[
+    ("none", "None"),
+    ("unknown", "Unknown"),
+    ("log", "Log"),
+    ("info", "Log"),
+    ("debug", "Debug"),
+    ("trace", "Debug"),
+    ("warn", "Warn"),
+    ("warning", "Warn"),
+    ("error", "Error"),
+    ("critical", "Critical"),
+    ("crit", "Critical"),
+    ("fatal", "Fatal"),
+];
+
+/// The `ErrorLevel` variant a `level = "..."` option names. Any other name is an error,
+/// so typos don't silently become `Other` levels.
+pub fn known(name: &LitStr) -> Result<TokenStream> {
+    let value = name.value().trim().to_lowercase();
+    match KNOWN.iter().find(|(known, _)| *known == value) {
+        Some((_, variant)) => {
+            let variant = syn::Ident::new(variant, name.span());
+            Ok(quote!(::traceback_error::ErrorLevel::#variant))
+        }
+        None => {
+            let names: Vec<&str> = KNOWN.iter().map(|(known, _)| *known).collect();
+            Err(Error::new(
+                name.span(),
+                format!(
+                    "unknown level `{}`, expected one of {}, or `custom_level` for other levels",
+                    name.value(),
+                    names.join(", ")
+                ),
+            ))
+        }
+    }
+}
+
+/// The `ErrorLevel::Other` a `custom_level = "..."` option names.
+pub fn custom(name: &LitStr) -> Result<TokenStream> {
+    let value = name.value().trim().to_string();
+    if value.is_empty() {
+        return Err(Error::new(name.span(), "level must not be empty"));
+    }
+    Ok(quote!(::traceback_error::ErrorLevel::Other(::std::string::String::from(#value))))
+}
@@ -0,0 +1,31 @@
+//! Procedural macros for `traceback-error`. Use them through the `derive` feature of
+//! `traceback-error`, which re-exports them.
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemFn};
+
+mod instrument;
+mod level;
+mod traceback;
+
+/// Derives `From<T> for TracebackError`, see `traceback_error::Traceback`.
+#[proc_macro_derive(Traceback, attributes(traceback))]
+pub fn derive_traceback(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    traceback::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Wraps the errors a function returns in a new chain node, see
+/// `traceback_error::instrument`.
+#[proc_macro_attribute]
+pub fn instrument(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut options = instrument::Options::default();
+    let parser = syn::meta::parser(|meta| options.parse(meta));
+    parse_macro_input!(args with parser);
+    let item = parse_macro_input!(item as ItemFn);
+    instrument::expand(options, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,240 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Attribute, Data, DeriveInput, Error, Fields, LitStr, Path, Result};
+
+use crate::level;
+
+// Set on the type, and overridden per variant
+#[derive(Default, Clone)]
+struct Options {
+    level: Option<TokenStream>,
+    code: Option<LitStr>,
+    message: Option<LitStr>,
+}
+
+fn parse_options(attrs: &[Attribute], mut options: Options) -> Result<Options> {
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("traceback"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let value: LitStr = meta.value()?.parse()?;
+            if meta.path.is_ident("level") {
+                options.level = Some(level::known(&value)?);
+            } else if meta.path.is_ident("custom_level") {
+                options.level = Some(level::custom(&value)?);
+            } else if meta.path.is_ident("code") {
+                options.code = Some(value);
+            } else if meta.path.is_ident("message") {
+                options.message = Some(value);
+            } else {
+                return Err(meta.error("expected `level`, `custom_level`, `code` or `message`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+// How a field is copied into `extra_data`
+enum FieldMode {
+    Serialize,
+    Debug,
+    Skip,
+}
+
+fn field_mode(attrs: &[Attribute]) -> Result<FieldMode> {
+    let mut mode = FieldMode::Serialize;
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("traceback"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                mode = FieldMode::Skip;
+            } else if meta.path.is_ident("debug") {
+                mode = FieldMode::Debug;
+            } else {
+                return Err(meta.error("expected `skip` or `debug`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(mode)
+}
+
+pub fn expand(input: DeriveInput) -> Result<TokenStream> {
+    let name = &input.ident;
+    let defaults = parse_options(&input.attrs, Options::default())?;
+    let arms = match &input.data {
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let options = parse_options(&variant.attrs, defaults.clone())?;
+                let ident = &variant.ident;
+                arm(&syn::parse_quote!(#name::#ident), &variant.fields, &options)
+            })
+            .collect::<Result<Vec<_>>>()?,
+        Data::Struct(data) => vec![arm(&syn::parse_quote!(#name), &data.fields, &defaults)?],
+        Data::Union(_) => {
+            return Err(Error::new(
+                name.span(),
+                "`Traceback` can only be derived for enums and structs",
+            ))
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::core::convert::From<#name #ty_generics>
+            for ::traceback_error::TracebackError #where_clause
+        {
+            #[track_caller]
+            fn from(error: #name #ty_generics) -> Self {
+                let location = ::std::panic::Location::caller();
+                let (message, level, code, extra_data): (
+                    ::std::string::String,
+                    ::traceback_error::ErrorLevel,
+                    ::std::option::Option<&str>,
+                    ::traceback_error::serde_json::Map<
+                        ::std::string::String,
+                        ::traceback_error::serde_json::Value,
+                    >,
+                ) = match &error {
+                    #(#arms)*
+                };
+                let mut traceback = ::traceback_error::TracebackError::new(
+                    message,
+                    location.file().to_string(),
+                    location.line(),
+                    level,
+                )
+                .with_module_path(::core::module_path!())
+                .with_extra_data(::traceback_error::serde_json::Value::Object(extra_data));
+                if let ::std::option::Option::Some(code) = code {
+                    traceback = traceback.with_diagnostic(
+                        ::traceback_error::diagnostic::Diagnostic::new().with_code(code),
+                    );
+                }
+                traceback.with_source(error)
+            }
+        }
+    })
+}
+
+// The match arm producing the message, level, code and extra data of one variant
+fn arm(path: &Path, fields: &Fields, options: &Options) -> Result<TokenStream> {
+    // Fields are bound to generated names, so they can't shadow the arm's own locals
+    let mut patterns = Vec::new();
+    let mut arguments = Vec::new();
+    let mut inserts = Vec::new();
+    let used = match &options.message {
+        Some(message) => format_arguments(&message.value()),
+        None => Vec::new(),
+    };
+    for (index, field) in fields.iter().enumerate() {
+        let binding = format_ident!("__tb_field_{}", index);
+        let (key, argument) = match &field.ident {
+            Some(ident) => {
+                patterns.push(quote!(#ident: #binding));
+                (ident.to_string(), ident.clone())
+            }
+            None => {
+                patterns.push(quote!(#binding));
+                (index.to_string(), format_ident!("_{}", index))
+            }
+        };
+        if used.contains(&key) {
+            arguments.push(quote!(#argument = #binding));
+        }
+        let value = match field_mode(&field.attrs)? {
+            FieldMode::Serialize => quote! {
+                ::traceback_error::serde_json::to_value(#binding).unwrap_or_else(|e| {
+                    ::traceback_error::serde_json::Value::String(::std::format!(
+                        "failed to serialize: {}",
+                        e
+                    ))
+                })
+            },
+            FieldMode::Debug => quote! {
+                ::traceback_error::serde_json::Value::String(::std::format!("{:?}", #binding))
+            },
+            FieldMode::Skip => continue,
+        };
+        inserts.push(quote!(extra_data.insert(#key.to_string(), #value);));
+    }
+
+    let pattern = match fields {
+        Fields::Named(_) => quote!(#path { #(#patterns),* }),
+        Fields::Unnamed(_) => quote!(#path ( #(#patterns),* )),
+        Fields::Unit => quote!(#path),
+    };
+    let message = match &options.message {
+        Some(message) => {
+            // Positional fields are passed as `_0`, `_1`, ... since arguments can't be
+            // named by a number
+            let message = LitStr::new(&bind_positional(&message.value()), message.span());
+            quote!(::std::format!(#message #(, #arguments)*))
+        }
+        None => quote!(::std::string::ToString::to_string(&error)),
+    };
+    let level = match &options.level {
+        Some(level) => level.clone(),
+        None => quote!(::traceback_error::ErrorLevel::Error),
+    };
+    let code = match &options.code {
+        Some(code) => quote!(::std::option::Option::Some(#code)),
+        None => quote!(::std::option::Option::None),
+    };
+    Ok(quote! {
+        #[allow(unused_variables)]
+        #pattern => {
+            #[allow(unused_mut)]
+            let mut extra_data = ::traceback_error::serde_json::Map::new();
+            #(#inserts)*
+            (#message, #level, #code, extra_data)
+        }
+    })
+}
+
+// The names and indexes of the arguments a format string refers to, such as `id` in
+// `{id:>8}`
+fn format_arguments(message: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '{' {
+            continue;
+        }
+        if chars.next_if_eq(&'{').is_some() {
+            continue;
+        }
+        let mut argument = String::new();
+        while let Some(c) = chars.next_if(|c| *c != ':' && *c != '}') {
+            argument.push(c);
+        }
+        let argument = argument.trim().to_string();
+        if !argument.is_empty() && !arguments.contains(&argument) {
+            arguments.push(argument);
+        }
+    }
+    arguments
+}
+
+// Turns `{0}` into `{_0}`, leaving escaped braces alone
+fn bind_positional(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        if c == '{' {
+            match chars.peek() {
+                Some('{') => out.push(chars.next().unwrap()),
+                Some(next) if next.is_ascii_digit() => out.push('_'),
+                _ => {}
+            }
+        }
+    }
+    out
+}